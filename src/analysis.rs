@@ -1,52 +1,2276 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
     collections::VecDeque,
-    time::{Instant, SystemTime},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::runner::CmdOutput;
+use crate::{ansi, runner::CmdOutput};
+
+pub mod eta;
+pub mod json;
+pub mod logfmt;
 
 pub struct Analyzer {
     pub samples: VecDeque<Sample>,
+    /// The events marked by `Options::annotate`, oldest first.
+    pub annotations: VecDeque<Annotation>,
+    /// Describes how recent outputs were processed. Only recorded if
+    /// `Options::debug` is set, except for warnings about ignored values.
+    pub diagnostics: VecDeque<String>,
+    options: Options,
+    /// The length of the current run's output that has already been scanned.
+    scanned_len: usize,
+    /// The largest value or maximum value seen so far.
+    seen_max: Option<f64>,
+    /// The first maximum value captured.
+    first_max: Option<f64>,
+    /// The number of consecutive values rejected by
+    /// `Options::reject_outliers`.
+    num_rejected_outliers: usize,
+    /// The pattern set by `Options::pattern` or a directive. Overrides the
+    /// default one.
+    pattern: Option<regex::Regex>,
+    /// The interval between runs requested by a directive. Taken by
+    /// `take_requested_interval`.
+    requested_interval: Option<Duration>,
+    /// The total measured by `Options::meter` in the completed runs.
+    meter_base: f64,
+    /// The number of captured strings that couldn't be parsed as values.
+    num_parse_errors: usize,
+    /// The last captured string that couldn't be parsed.
+    last_parse_error: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Options controlling how values are extracted from a command's output.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Fall back to the first standalone number in the output if the pattern
+    /// doesn't match.
+    pub autodetect: bool,
+    /// Determines `Sample::max`.
+    pub max_mode: MaxMode,
+    /// How captured values are interpreted.
+    pub parse_mode: ParseMode,
+    /// How multiple matches in an output are combined. Unless this is
+    /// `Aggregate::Latest` or `Aggregate::All`, an output is processed only
+    /// after the command exits.
+    pub aggregate: Aggregate,
+    /// Record `Analyzer::diagnostics`.
+    pub debug: bool,
+    /// Remove ANSI escape sequences before matching.
+    pub strip_ansi: bool,
+    /// Only scan the lines matching this.
+    pub line_match: Option<regex::Regex>,
+    /// Don't scan the lines matching this.
+    pub line_ignore: Option<regex::Regex>,
+    /// Record an `Annotation` for each line matching this.
+    pub annotate: Option<regex::Regex>,
+    /// The window size of the rolling median filter applied by
+    /// `Analyzer::filtered_samples`.
+    pub median: Option<usize>,
+    /// How a decrease in the value is treated.
+    pub reset_mode: ResetMode,
+    /// Reject a value that jumps implausibly far from the recent trend.
+    pub reject_outliers: bool,
+    /// A sample identical to the last one is discarded unless this much time
+    /// has passed since it or the command has exited.
+    pub min_sample_interval: Duration,
+    /// The initial period of each segment excluded from the rate and the ETA.
+    pub warmup: Option<Warmup>,
+    /// Measure the size of stdout instead of scanning it for values.
+    pub meter: Option<Meter>,
+    /// Add up the measurements of `meter` across runs.
+    pub cumulative: bool,
+    /// The captured values are the remaining amounts. Record the maximum
+    /// value minus them instead.
+    pub invert: bool,
+    /// A regular expression that captures the value and optionally the
+    /// maximum value, replacing the default one. See `parse_pattern`.
+    pub pattern: Option<regex::Regex>,
+    /// Discard the samples older than this relative to the latest one.
+    pub window: Option<Duration>,
+    /// Smooth the rate series. If set, `Progress::recent_rate` is the latest
+    /// smoothed rate, and `Progress::eta` is based on it instead of the
+    /// average rate.
+    pub smooth: Option<Smoothing>,
+    /// Only fit the ETA to the samples from this long before the latest one.
+    pub eta_window: Option<Duration>,
+    /// The value staying the same for this long is reported by
+    /// `Analyzer::stalled_for`.
+    pub stall_timeout: Option<Duration>,
+    /// Parse the output as JSON and extract the values referenced by these
+    /// JSON pointers instead of matching a pattern.
+    pub json: Option<JsonPointers>,
+    /// Extract the values of these keys from logfmt-style `key=value` pairs
+    /// instead of matching a pattern.
+    pub kv: Option<logfmt::Keys>,
+    /// Interpret the suffixes without `i` (`k`, `M`, `G`, ...) in captured
+    /// numbers as powers of 1024 instead of 1000.
+    pub binary_prefixes: bool,
+}
+
+/// The JSON pointers of `Options::json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPointers {
+    pub value: String,
+    pub max: Option<String>,
+}
+
+/// The same as the command-line defaults.
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            autodetect: true,
+            max_mode: MaxMode::Captured,
+            parse_mode: ParseMode::Number,
+            aggregate: Aggregate::Latest,
+            debug: false,
+            strip_ansi: true,
+            line_match: None,
+            line_ignore: None,
+            annotate: None,
+            median: None,
+            reset_mode: ResetMode::None,
+            reject_outliers: false,
+            min_sample_interval: Duration::from_secs(0),
+            warmup: None,
+            meter: None,
+            cumulative: false,
+            invert: false,
+            pattern: None,
+            window: None,
+            smooth: None,
+            eta_window: None,
+            stall_timeout: None,
+            json: None,
+            kv: None,
+            binary_prefixes: false,
+        }
+    }
+}
+
+/// The index of the first capture group that may name an extra series. The
+/// preceding ones capture the value and the maximum value.
+const EXTRA_SERIES_START: usize = 3;
+
+/// The maximum number of entries retained in `Analyzer::samples`.
+const MAX_SAMPLES: usize = 1000;
+
+/// The number of entries retained in `Analyzer::diagnostics`.
+const MAX_DIAGNOSTICS: usize = 5;
+
+/// Starts a directive line. See `Analyzer::consume_directives`.
+const DIRECTIVE_PREFIX: &str = "#pvfilt";
+
+/// The number of recent samples `Options::reject_outliers` examines.
+const OUTLIER_WINDOW: usize = 10;
+
+/// How many times larger than the typical change (or the magnitude of the
+/// last value) a change has to be to be rejected as an outlier.
+const OUTLIER_RATIO: f64 = 100.0;
+
+/// The number of consecutive outliers after which they are accepted as a
+/// genuine shift.
+const MAX_REJECTED_OUTLIERS: usize = 3;
+
+/// The number of entries retained in `Analyzer::annotations`.
+const MAX_ANNOTATIONS: usize = 100;
+
+/// The number of samples used to calculate `Progress::recent_rate`.
+const RECENT_RATE_SAMPLES: usize = 5;
+
+/// Specifies how captured values are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    /// Decimal numbers, optionally with thousands separators (`3,214,000`)
+    /// and SI or IEC suffixes (`1.5G`, `12MiB`).
+    Number,
+    /// Durations in the `[HH:]MM:SS[.fff]` format, converted to seconds.
+    Duration,
+}
+
+impl ParseMode {
+    pub const NAMES: &'static [&'static str] = &["number", "duration"];
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Parse a captured value. See `Options::binary_prefixes` for
+    /// `binary_prefixes`.
+    fn parse(self, s: &str, binary_prefixes: bool) -> Option<f64> {
+        match self {
+            ParseMode::Number => parse_number(s, binary_prefixes),
+            ParseMode::Duration => parse_clock_duration(s),
+        }
+    }
+}
+
+impl FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "number" => Ok(ParseMode::Number),
+            "duration" => Ok(ParseMode::Duration),
+            _ => Err(format!("unknown parse mode: {}", s)),
+        }
+    }
+}
+
+/// Specifies how multiple matches in an output are combined into a sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    /// Use the last match in the lines output since the last time. New lines
+    /// are processed while the command is running, so this records the
+    /// latest value as it changes.
+    Latest,
+    /// Use the first match.
+    First,
+    /// Use the last match.
+    Last,
+    /// Add up the values and maximum values.
+    Sum,
+    /// Use the largest value and maximum value.
+    Max,
+    /// Average the values and maximum values.
+    Mean,
+    /// Use the k-th match (1-based).
+    Nth(usize),
+    /// Record every match as a separate sample.
+    All,
+}
+
+impl Aggregate {
+    pub fn name(self) -> String {
+        match self {
+            Aggregate::Latest => "latest".to_owned(),
+            Aggregate::First => "first".to_owned(),
+            Aggregate::Last => "last".to_owned(),
+            Aggregate::Sum => "sum".to_owned(),
+            Aggregate::Max => "max".to_owned(),
+            Aggregate::Mean => "mean".to_owned(),
+            Aggregate::Nth(k) => format!("nth:{}", k),
+            Aggregate::All => "all".to_owned(),
+        }
+    }
+
+    /// Combine `(value, max)` pairs into the samples to record, each with the
+    /// index of the pair it's attributed to. Maximum values are combined only
+    /// from the pairs that have one.
+    fn apply(self, pairs: &[(f64, Option<f64>)]) -> Vec<(usize, (f64, Option<f64>))> {
+        if pairs.is_empty() {
+            return Vec::new();
+        }
+
+        let values = pairs.iter().map(|p| p.0);
+        let maxes = pairs.iter().filter_map(|p| p.1);
+        let num_maxes = maxes.clone().count();
+        let max_if_any = |x: f64| if num_maxes > 0 { Some(x) } else { None };
+
+        match self {
+            Aggregate::First => vec![(0, pairs[0])],
+            Aggregate::Latest | Aggregate::Last => {
+                vec![(pairs.len() - 1, pairs[pairs.len() - 1])]
+            }
+            Aggregate::Nth(k) => pairs
+                .get(k - 1)
+                .map(|&pair| (k - 1, pair))
+                .into_iter()
+                .collect(),
+            Aggregate::All => pairs.iter().copied().enumerate().collect(),
+            Aggregate::Sum => vec![(0, (values.sum(), max_if_any(maxes.sum())))],
+            Aggregate::Max => vec![(
+                0,
+                (
+                    values.fold(f64::NEG_INFINITY, f64::max),
+                    max_if_any(maxes.fold(f64::NEG_INFINITY, f64::max)),
+                ),
+            )],
+            Aggregate::Mean => vec![(
+                0,
+                (
+                    values.sum::<f64>() / pairs.len() as f64,
+                    max_if_any(maxes.sum::<f64>() / num_maxes as f64),
+                ),
+            )],
+        }
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(Aggregate::Latest),
+            "first" => Ok(Aggregate::First),
+            "last" => Ok(Aggregate::Last),
+            "sum" => Ok(Aggregate::Sum),
+            "max" => Ok(Aggregate::Max),
+            "mean" => Ok(Aggregate::Mean),
+            "all" => Ok(Aggregate::All),
+            _ => match s.strip_prefix("nth:").map(str::parse) {
+                Some(Ok(k)) if k > 0 => Ok(Aggregate::Nth(k)),
+                Some(_) => Err(format!("invalid match index (must be 1 or more): {}", s)),
+                None => Err(format!("unknown aggregation: {}", s)),
+            },
+        }
+    }
+}
+
+/// Parse `--match-line`, which is limited to the `Aggregate` modes that select
+/// matches.
+pub fn parse_match_selection(s: &str) -> Result<Aggregate, String> {
+    match s.parse()? {
+        aggregate @ (Aggregate::First | Aggregate::Last | Aggregate::Nth(_) | Aggregate::All) => {
+            Ok(aggregate)
+        }
+        _ => Err(format!(
+            "invalid match selection (expected `first`, `last`, `nth:K`, or `all`): {}",
+            s
+        )),
+    }
+}
+
+/// Specifies how a decrease in the value, e.g., a counter restarting from zero
+/// on every run, is treated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetMode {
+    /// Treat it as an ordinary change.
+    None,
+    /// Start a new segment. The rate is not calculated across segments, and
+    /// the progress only covers the latest one.
+    Segment,
+    /// Offset the subsequent values by the value before the decrease, making
+    /// them monotonic.
+    Cumulative,
+}
+
+impl ResetMode {
+    pub const NAMES: &'static [&'static str] = &["none", "segment", "cumulative"];
+}
+
+impl FromStr for ResetMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ResetMode::None),
+            "segment" => Ok(ResetMode::Segment),
+            "cumulative" => Ok(ResetMode::Cumulative),
+            _ => Err(format!("unknown reset mode: {}", s)),
+        }
+    }
+}
+
+/// Specifies what `Options::meter` measures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Meter {
+    Bytes,
+    Lines,
+}
+
+impl Meter {
+    pub const NAMES: &'static [&'static str] = &["bytes", "lines"];
+}
+
+impl FromStr for Meter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bytes" => Ok(Meter::Bytes),
+            "lines" => Ok(Meter::Lines),
+            _ => Err(format!("unknown meter: {}", s)),
+        }
+    }
+}
+
+/// The pattern used by `--percent`. Matches percentages like `42%` and
+/// `42.7 %`, which are recorded out of a fixed maximum value of 100.
+pub const PERCENT_PATTERN: &str = r"\b([0-9]+(?:\.[0-9]+)?) ?%";
+
+/// Parse a pattern replacing the default one. The first capture group
+/// captures the value, and the second one, if any, captures the maximum value.
+pub fn parse_pattern(s: &str) -> Result<regex::Regex, String> {
+    let re = regex::Regex::new(s).map_err(|e| e.to_string())?;
+    if re.captures_len() < 2 {
+        return Err("the pattern must have a capture group".to_owned());
+    }
+    Ok(re)
+}
+
+/// The length of the warmup period, which is excluded from the rate and the
+/// ETA because commands often start slowly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warmup {
+    Duration(Duration),
+    Samples(usize),
+}
+
+impl FromStr for Warmup {
+    type Err = String;
+
+    /// Parse a sample count (e.g., `5`) or a duration (e.g., `30s`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(count) = s.parse() {
+            Ok(Warmup::Samples(count))
+        } else {
+            humantime::parse_duration(s)
+                .map(Warmup::Duration)
+                .map_err(|e| format!("invalid warmup: {}: {}", s, e))
+        }
+    }
+}
+
+/// Specifies how the rate series is smoothed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// The simple moving average of this many rates.
+    MovingAverage(usize),
+    /// The exponentially weighted moving average with this smoothing factor in
+    /// `(0, 1]`. Larger factors follow the latest rate more closely.
+    Ewma(f64),
+}
+
+impl Smoothing {
+    /// Smooth `(time, rate)` pairs in chronological order.
+    pub fn apply(self, data: impl IntoIterator<Item = (f64, f64)>) -> Vec<(f64, f64)> {
+        match self {
+            Smoothing::MovingAverage(window) => {
+                let data: Vec<_> = data.into_iter().collect();
+                (0..data.len())
+                    .map(|i| {
+                        let range = &data[(i + 1).saturating_sub(window)..=i];
+                        let sum: f64 = range.iter().map(|&(_, v)| v).sum();
+                        (data[i].0, sum / range.len() as f64)
+                    })
+                    .collect()
+            }
+            Smoothing::Ewma(alpha) => data
+                .into_iter()
+                .scan(None, |avg: &mut Option<f64>, (t, v)| {
+                    let next = avg.map_or(v, |avg| avg + alpha * (v - avg));
+                    *avg = Some(next);
+                    Some((t, next))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for Smoothing {
+    type Err = String;
+
+    /// Parse `sma:N` or `ewma:ALPHA`, e.g., `ewma:0.3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid smoothing (expected `sma:N` or `ewma:ALPHA`): {}",
+                s
+            )
+        };
+        let mut parts = s.splitn(2, ':');
+        let (kind, param) = (parts.next(), parts.next().ok_or_else(invalid)?);
+        match kind {
+            Some("sma") => match param.parse() {
+                Ok(window) if window > 0 => Ok(Smoothing::MovingAverage(window)),
+                _ => Err(invalid()),
+            },
+            Some("ewma") => match param.parse() {
+                Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(Smoothing::Ewma(alpha)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Parse a decimal number, allowing thousands separators and an SI or IEC
+/// suffix optionally followed by `B`, e.g., `3,214,000`, `1.5G`, `12MiB`.
+/// A suffix without `i` is a power of 1000, or 1024 if `binary_prefixes`.
+fn parse_number(s: &str, binary_prefixes: bool) -> Option<f64> {
+    lazy_static::lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(
+            r"^([+-]?(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]*)(?:\.[0-9]+)?) ?(?:([kKMGTPE])(i)?)?B?$"
+        ).unwrap();
+    }
+
+    if let Ok(x) = s.parse() {
+        return Some(x);
+    }
+
+    let caps = RE.captures(s)?;
+    let mantissa: f64 = caps[1].replace(',', "").parse().ok()?;
+    let exponent = caps.get(2).map_or(0, |m| {
+        "KMGTPE".find(&m.as_str().to_ascii_uppercase()).unwrap() as i32 + 1
+    });
+    let base: f64 = if caps.get(3).is_some() || binary_prefixes {
+        1024.0
+    } else {
+        1000.0
+    };
+    Some(mantissa * base.powi(exponent))
+}
+
+/// Parse a duration in the `[HH:]MM:SS[.fff]` format into seconds. Each field
+/// must consist of digits; signs and exponents are rejected.
+fn parse_clock_duration(s: &str) -> Option<f64> {
+    let fields: Vec<&str> = s.split(':').collect();
+    if fields.len() < 2 || fields.len() > 3 {
+        return None;
+    }
+
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (seconds, hours_minutes) = fields.split_last()?;
+    let mut seconds_parts = seconds.splitn(2, '.');
+    if !hours_minutes.iter().all(|field| is_digits(field)) || !seconds_parts.all(is_digits) {
+        return None;
+    }
+
+    let mut total = 0.0;
+    for field in hours_minutes {
+        total = total * 60.0 + field.parse::<u64>().ok()? as f64;
+    }
+    Some(total * 60.0 + seconds.parse::<f64>().ok()?)
+}
+
+/// Specifies how `Sample::max` is determined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaxMode {
+    /// Use the maximum value captured along with each value.
+    Captured,
+    /// Use the largest value or maximum value seen so far.
+    Seen,
+    /// Use the first maximum value captured.
+    First,
+    /// Use the given value.
+    Fixed(f64),
+}
+
+/// A notable event found in the output.
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    pub instant: Instant,
+    /// The first capture group of `Options::annotate`, or the whole line if
+    /// there's none.
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
 pub struct Sample {
     // TODO: Customization
     pub instant: Instant,
     pub time: SystemTime,
     pub value: f64,
-    pub max: f64,
+    /// The maximum value, if known.
+    pub max: Option<f64>,
+    /// The line the value was found in, if known.
+    pub line: Option<Arc<str>>,
+    /// The values of the extra series named by `Analyzer::series_names`.
+    /// `None` if a group didn't participate in the match.
+    pub series: Vec<Option<f64>>,
+}
+
+/// Metrics derived from the retained samples.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// The latest value.
+    pub value: f64,
+    /// The latest maximum value, if known.
+    pub max: Option<f64>,
+    /// The average increase rate (per second) over the retained samples.
+    pub rate: f64,
+    /// The increase rate (per second) over the last few samples.
+    pub recent_rate: Option<f64>,
+    /// The estimated time to completion in seconds.
+    pub eta: Option<f64>,
+    /// The confidence interval of `eta`. See `eta::Estimate::range`.
+    pub eta_range: Option<(f64, f64)>,
+    /// The time span (in seconds) of the samples `rate` was calculated from.
+    pub elapsed: f64,
+    /// The warmup period hasn't elapsed yet. `rate` is NaN, and `recent_rate`
+    /// and `eta` are `None`.
+    pub warming_up: bool,
+}
+
+impl Progress {
+    /// Get the ratio of the value to the maximum value. May be out of the
+    /// range `[0, 1]` or non-finite.
+    pub fn ratio(&self) -> Option<f64> {
+        self.max.map(|max| self.value / max)
+    }
 }
 
 impl Analyzer {
-    pub fn new() -> Self {
+    pub fn new(options: Options) -> Self {
+        let pattern = options.pattern.clone();
         Self {
             samples: VecDeque::new(),
+            annotations: VecDeque::new(),
+            diagnostics: VecDeque::new(),
+            options,
+            scanned_len: 0,
+            seen_max: None,
+            first_max: None,
+            num_rejected_outliers: 0,
+            pattern,
+            requested_interval: None,
+            meter_base: 0.0,
+            num_parse_errors: 0,
+            last_parse_error: None,
         }
     }
 
+    /// Insert samples from a previous session before the new ones.
+    pub fn preload(&mut self, samples: impl IntoIterator<Item = Sample>) {
+        for sample in samples {
+            let seen_max = sample.max.map_or(sample.value, |max| max.max(sample.value));
+            self.seen_max = Some(self.seen_max.map_or(seen_max, |x| x.max(seen_max)));
+            if self.first_max.is_none() {
+                self.first_max = sample.max;
+            }
+            self.samples.push_back(sample);
+        }
+
+        self.trim_samples();
+    }
+
+    /// Process the output of a command. This is called repeatedly as the
+    /// command produces output, so only the part that hasn't been seen yet is
+    /// examined. An incomplete line is left for later unless the command has
+    /// exited.
     pub fn process_output(&mut self, outp: &CmdOutput) {
         // TODO: Customize the detection rule
         lazy_static::lazy_static! {
             static ref RE: regex::Regex = regex::Regex::new("([0-9]+)/([0-9]+)").unwrap();
+            static ref RE_NUMBER: regex::Regex =
+                regex::Regex::new(r"\b[0-9]+(?:\.[0-9]+)?\b").unwrap();
+            static ref RE_DURATION: regex::Regex = regex::Regex::new(
+                r"([0-9]+(?::[0-9]+){1,2}(?:\.[0-9]+)?)/([0-9]+(?::[0-9]+){1,2}(?:\.[0-9]+)?)"
+            ).unwrap();
+            static ref RE_SINGLE_DURATION: regex::Regex =
+                regex::Regex::new(r"\b[0-9]+(?::[0-9]+){1,2}(?:\.[0-9]+)?\b").unwrap();
+        }
+
+        // `scanned_len` includes the part of the output discarded by the
+        // runner, which is always a whole number of lines
+        let stdout = &outp.stdout;
+        let start = (self.scanned_len.saturating_sub(outp.stdout_offset)).min(stdout.len());
+        let start = if stdout.is_char_boundary(start) {
+            start
+        } else {
+            0
+        };
+        let end = if outp.status.is_some() {
+            stdout.len()
+        } else if !matches!(self.options.aggregate, Aggregate::Latest | Aggregate::All) {
+            // Matches are combined over the entire output of a run
+            start
+        } else {
+            stdout[start..].rfind('\n').map_or(start, |i| start + i + 1)
+        };
+        let text = &stdout[start..end];
+        self.scanned_len = if outp.status.is_some() {
+            // The next output belongs to a new run
+            0
+        } else {
+            (outp.stdout_offset + end).max(self.scanned_len)
+        };
+
+        // Only the new part is stripped, so a long output is not processed
+        // over and over
+        let text: Cow<str> = if self.options.strip_ansi {
+            ansi::strip(text).into()
+        } else {
+            text.into()
+        };
+        let text: &str = &text;
+
+        self.find_annotations(text);
+
+        if let Some(meter) = self.options.meter {
+            self.process_meter(outp, meter);
+            return;
+        }
+
+        let text = self.consume_directives(text);
+        let filtered = self.filter_lines(&text);
+        let text: &str = &filtered;
+
+        if text.is_empty() {
+            return;
+        }
+
+        let mut diagnostic = String::new();
+        // The samples to record, each with the byte offset in `text` of the
+        // match that produced it and the values of the extra series
+        let mut selected = Vec::new();
+
+        let aggregate = self.options.aggregate;
+
+        let parse_mode = self.options.parse_mode;
+        let (re, re_single) = match parse_mode {
+            ParseMode::Number => (&*RE, &*RE_NUMBER),
+            ParseMode::Duration => (&*RE_DURATION, &*RE_SINGLE_DURATION),
+        };
+        let re = self.pattern.as_ref().unwrap_or(re);
+        let binary_prefixes = self.options.binary_prefixes;
+        let parse_errors = RefCell::new(Vec::new());
+        let parse = |s: &str| {
+            let value = parse_mode.parse(s, binary_prefixes);
+            if value.is_none() {
+                parse_errors.borrow_mut().push(s.to_owned());
+            }
+            value
+        };
+
+        let matches: Vec<_> = if self.options.json.is_some() || self.options.kv.is_some() {
+            Vec::new()
+        } else {
+            re.captures_iter(text).collect()
+        };
+
+        if let Some(pointers) = &self.options.json {
+            let (pairs, offsets) = extract_json(text, pointers, parse);
+            if self.options.debug {
+                diagnostic = match pairs.first() {
+                    Some(_) if pairs.len() > 1 => {
+                        format!("Found {} JSON documents with the value", pairs.len())
+                    }
+                    Some(_) => "Found a JSON document with the value".to_owned(),
+                    None => "No JSON document with the value".to_owned(),
+                };
+            }
+            selected = (aggregate.apply(&pairs).into_iter())
+                .map(|(i, pair)| (Some(offsets[i]), Vec::new(), pair))
+                .collect();
+        } else if let Some(keys) = &self.options.kv {
+            let (pairs, offsets) = extract_kv(text, keys, parse);
+            if self.options.debug {
+                diagnostic = format!("Found `{}` in {} line(s)", keys.value, pairs.len());
+            }
+            selected = (aggregate.apply(&pairs).into_iter())
+                .map(|(i, pair)| (Some(offsets[i]), Vec::new(), pair))
+                .collect();
+        } else if let Some(mat) = matches.first() {
+            if self.options.debug {
+                let groups: Vec<_> = mat
+                    .iter()
+                    .skip(1)
+                    .map(|m| m.map_or("", |m| m.as_str()))
+                    .collect();
+                diagnostic = format!("Matched {:?}, captured {:?}", &mat[0], groups);
+                if matches.len() > 1 {
+                    diagnostic += &format!(" (+{} more)", matches.len() - 1);
+                }
+            }
+            let (indices, pairs): (Vec<_>, Vec<_>) = matches
+                .iter()
+                .enumerate()
+                .filter_map(|(i, mat)| {
+                    // A user-supplied pattern may lack the second group
+                    let max = mat.get(2).and_then(|m| parse(m.as_str()));
+                    parse(mat.get(1)?.as_str()).map(|value| (i, (value, max)))
+                })
+                .unzip();
+            selected = (aggregate.apply(&pairs).into_iter())
+                .map(|(i, pair)| {
+                    let mat = &matches[indices[i]];
+                    let series = (EXTRA_SERIES_START..mat.len())
+                        .filter(|&i| re.capture_names().nth(i).flatten().is_some())
+                        .map(|i| mat.get(i).and_then(|m| parse(m.as_str())))
+                        .collect();
+                    (Some(mat.get(0).unwrap().start()), series, pair)
+                })
+                .collect();
+        } else if self.options.autodetect {
+            let (plausible, rejected): (Vec<_>, Vec<_>) = re_single
+                .find_iter(text)
+                .partition(|mat| is_plausible_number(text, mat));
+            let mat = if matches!(aggregate, Aggregate::Latest | Aggregate::Last) {
+                plausible.last()
+            } else {
+                plausible.first()
+            }
+            .copied();
+            if self.options.debug {
+                diagnostic = if let Some(mat) = mat {
+                    format!("No match, autodetected {:?}", mat.as_str())
+                } else {
+                    "No match, nothing autodetected".to_owned()
+                };
+                let rejected: Vec<_> = rejected.iter().map(|mat| mat.as_str()).collect();
+                if !rejected.is_empty() {
+                    diagnostic += &format!(" (rejected {:?})", rejected);
+                }
+            }
+            selected.extend(mat.and_then(|mat| {
+                let value = parse(mat.as_str())?;
+                Some((Some(mat.start()), Vec::new(), (value, None)))
+            }));
+        } else {
+            diagnostic = "No match".to_owned();
+        }
+
+        let parse_errors = parse_errors.into_inner();
+        if let Some(last) = parse_errors.last() {
+            self.num_parse_errors += parse_errors.len();
+            self.last_parse_error = Some(last.clone());
+            if self.options.debug {
+                diagnostic += &format!(" (failed to parse {:?})", parse_errors);
+            }
+        }
+
+        // The samples from an output share a timestamp even if there are
+        // many (`Aggregate::All`) because they were observed at once
+        let instant = Instant::now();
+        let mut warning = None;
+
+        for (matched_at, series, (value, max)) in selected {
+            if self.options.debug {
+                diagnostic += &format!(" → value = {}", value);
+                if let Some(max) = max {
+                    diagnostic += &format!(", max = {}", max);
+                }
+            }
+
+            // A number too large to represent parses into infinity, which
+            // would break the charts and the estimation
+            let value_max = match (value, max) {
+                (value, _) if !value.is_finite() => {
+                    warning = Some("ignored the non-finite value");
+                    None
+                }
+                (value, Some(max)) if !max.is_finite() => {
+                    warning = Some("ignored the non-finite maximum value");
+                    Some((value, None))
+                }
+                (value, max) if self.stored_value(value, max).is_none() => {
+                    warning = Some("ignored the value because the maximum value is unknown");
+                    None
+                }
+                value_max => Some(value_max),
+            };
+
+            let value_max = match value_max {
+                Some((value, max))
+                    if self.options.reject_outliers
+                        && self.is_outlier(self.stored_value(value, max).unwrap()) =>
+                {
+                    self.num_rejected_outliers += 1;
+                    if self.options.debug {
+                        diagnostic += " (rejected as an outlier)";
+                    }
+                    None
+                }
+                value_max => {
+                    self.num_rejected_outliers = 0;
+                    value_max
+                }
+            };
+
+            if let Some((value, max)) = value_max {
+                let line = matched_at.map(|i| line_at(text, i).into());
+                self.push_sample(value, max, line, series, instant, outp.status.is_some());
+            }
         }
 
-        if let Some(mat) = RE.captures(&outp.stdout) {
-            // TODO: Annotate the text with span information
-            let instant = Instant::now();
-            let time = SystemTime::now();
-            let value: f64 = mat[1].parse().unwrap();
-            let max: f64 = mat[2].parse().unwrap();
+        if let Some(warning) = warning {
+            if self.options.debug {
+                diagnostic += &format!(" ({})", warning);
+            } else {
+                diagnostic = format!("Warning: {}", warning);
+            }
+        }
+
+        if self.options.debug || warning.is_some() {
+            self.push_diagnostic(diagnostic);
+        }
+    }
+
+    /// Record a sample unless it's redundant. `exited` indicates that it's
+    /// from the final output of a run.
+    fn push_sample(
+        &mut self,
+        value: f64,
+        max: Option<f64>,
+        line: Option<Arc<str>>,
+        series: Vec<Option<f64>>,
+        instant: Instant,
+        exited: bool,
+    ) {
+        let stored_value = self.stored_value(value, max).unwrap_or(value);
+        let max = self.determine_max(value, max);
+        let value = stored_value;
+        let time = SystemTime::now();
+
+        let redundant = self.samples.back().map_or(false, |last| {
+            last.value == value
+                && last.max == max
+                && last.series == series
+                && instant.duration_since(last.instant) < self.options.min_sample_interval
+        });
+
+        if !redundant || exited {
             self.samples.push_back(Sample {
                 instant,
                 time,
                 value,
                 max,
+                line,
+                series,
             });
         }
 
-        if self.samples.len() > 1000 {
+        self.trim_samples();
+    }
+
+    /// Discard the samples exceeding `MAX_SAMPLES` or outside
+    /// `Options::window`.
+    fn trim_samples(&mut self) {
+        let window_start = self
+            .options
+            .window
+            .zip(self.samples.back())
+            .and_then(|(window, last)| last.instant.checked_sub(window));
+
+        while self.samples.len() > MAX_SAMPLES
+            || (self.samples.front())
+                .zip(window_start)
+                .map_or(false, |(first, start)| first.instant < start)
+        {
             self.samples.pop_front();
         }
     }
+
+    /// Implements `Options::meter`.
+    fn process_meter(&mut self, outp: &CmdOutput, meter: Meter) {
+        let size = match meter {
+            Meter::Bytes => outp.stdout_len,
+            Meter::Lines => outp.stdout_num_lines,
+        } as f64;
+        let value = self.meter_base + size;
+
+        if outp.status.is_some() && self.options.cumulative {
+            self.meter_base = value;
+        }
+
+        self.push_sample(
+            value,
+            None,
+            None,
+            Vec::new(),
+            Instant::now(),
+            outp.status.is_some(),
+        );
+    }
+
+    fn push_diagnostic(&mut self, diagnostic: String) {
+        self.diagnostics.push_back(diagnostic);
+        if self.diagnostics.len() > MAX_DIAGNOSTICS {
+            self.diagnostics.pop_front();
+        }
+    }
+
+    /// Get the names of the extra series recorded in `Sample::series`, which
+    /// are the named capture groups of the pattern following the value and
+    /// the maximum value.
+    pub fn series_names(&self) -> Vec<&str> {
+        self.pattern.as_ref().map_or_else(Vec::new, |re| {
+            re.capture_names()
+                .skip(EXTRA_SERIES_START)
+                .flatten()
+                .collect()
+        })
+    }
+
+    /// Get the name of the capture group for the value, if any.
+    pub fn value_name(&self) -> Option<&str> {
+        self.pattern.as_ref()?.capture_names().nth(1).flatten()
+    }
+
+    /// Get the interval between runs requested by a directive since the last
+    /// call.
+    pub fn take_requested_interval(&mut self) -> Option<Duration> {
+        self.requested_interval.take()
+    }
+
+    /// Apply and remove directive lines, e.g.,
+    /// `#pvfilt interval=2s pattern=(\d+)/(\d+)`.
+    ///
+    /// A directive consists of whitespace-separated `KEY=VALUE` pairs. The
+    /// known keys are:
+    ///
+    ///  - `interval`: The interval between runs in watch mode, e.g., `2s`.
+    ///  - `pattern`: A regular expression that captures the value and
+    ///    optionally the maximum value, replacing the default one.
+    ///
+    /// Unknown keys are ignored. Values can't contain whitespace.
+    fn consume_directives<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        if !text.contains(DIRECTIVE_PREFIX) {
+            return text.into();
+        }
+
+        let mut rest = String::new();
+        for line in text.lines() {
+            match line.trim().strip_prefix(DIRECTIVE_PREFIX) {
+                Some(args) if args.is_empty() || args.starts_with(char::is_whitespace) => {
+                    for arg in args.split_whitespace() {
+                        if let Err(e) = self.apply_directive_arg(arg) {
+                            self.push_diagnostic(format!("Warning: directive `{}`: {}", arg, e));
+                        }
+                    }
+                }
+                _ => {
+                    rest.push_str(line);
+                    rest.push('\n');
+                }
+            }
+        }
+        rest.into()
+    }
+
+    fn apply_directive_arg(&mut self, arg: &str) -> Result<(), String> {
+        let (key, value) = match arg.find('=') {
+            Some(i) => (&arg[..i], &arg[i + 1..]),
+            None => return Err("expected KEY=VALUE".to_owned()),
+        };
+
+        match key {
+            "interval" => {
+                let interval = humantime::parse_duration(value).map_err(|e| e.to_string())?;
+                self.requested_interval = Some(interval);
+            }
+            "pattern" => {
+                self.pattern = Some(parse_pattern(value)?);
+            }
+            _ if self.options.debug => {
+                self.push_diagnostic(format!("Ignored an unknown directive key `{}`", key));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Check if `value` deviates from the recent trend by orders of magnitude.
+    fn is_outlier(&self, value: f64) -> bool {
+        if self.num_rejected_outliers >= MAX_REJECTED_OUTLIERS {
+            return false;
+        }
+
+        let recent = self
+            .samples
+            .range(self.samples.len().saturating_sub(OUTLIER_WINDOW)..);
+        let mut steps: Vec<f64> = recent
+            .clone()
+            .zip(recent.skip(1))
+            .map(|(s0, s1)| (s1.value - s0.value).abs())
+            .collect();
+        if steps.len() < 2 {
+            // Not enough samples to tell the trend
+            return false;
+        }
+        steps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let typical_step = steps[steps.len() / 2];
+
+        let last = self.samples.back().unwrap().value;
+        let scale = typical_step.max(last.abs()).max(1.0);
+        (value - last).abs() > scale * OUTLIER_RATIO
+    }
+
+    /// Record the lines matching `Options::annotate` in `annotations`.
+    fn find_annotations(&mut self, text: &str) {
+        let re = match &self.options.annotate {
+            Some(re) => re,
+            None => return,
+        };
+
+        let instant = Instant::now();
+        for line in text.lines() {
+            if let Some(caps) = re.captures(line) {
+                let text = caps.get(1).map_or(line, |m| m.as_str());
+                self.annotations.push_back(Annotation {
+                    instant,
+                    text: text.trim().to_owned(),
+                });
+            }
+        }
+
+        while self.annotations.len() > MAX_ANNOTATIONS {
+            self.annotations.pop_front();
+        }
+    }
+
+    /// Remove the lines excluded by `Options::line_match` and
+    /// `Options::line_ignore`.
+    fn filter_lines<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let (line_match, line_ignore) = (&self.options.line_match, &self.options.line_ignore);
+        if line_match.is_none() && line_ignore.is_none() {
+            return text.into();
+        }
+
+        text.lines()
+            .filter(|line| line_match.as_ref().map_or(true, |re| re.is_match(line)))
+            .filter(|line| !line_ignore.as_ref().map_or(false, |re| re.is_match(line)))
+            .flat_map(|line| vec![line, "\n"])
+            .collect::<String>()
+            .into()
+    }
+
+    /// Determine `Sample::max` according to `MaxMode`.
+    fn determine_max(&mut self, value: f64, captured_max: Option<f64>) -> Option<f64> {
+        let max = self.peek_max(value, captured_max);
+
+        let seen_max = captured_max.map_or(value, |max| max.max(value));
+        self.seen_max = Some(self.seen_max.map_or(seen_max, |x| x.max(seen_max)));
+
+        if self.first_max.is_none() {
+            self.first_max = captured_max;
+        }
+
+        max
+    }
+
+    /// Get what `determine_max` would return without recording the values.
+    fn peek_max(&self, value: f64, captured_max: Option<f64>) -> Option<f64> {
+        let seen_max = captured_max.map_or(value, |max| max.max(value));
+        match self.options.max_mode {
+            MaxMode::Captured => captured_max,
+            MaxMode::Seen => Some(self.seen_max.map_or(seen_max, |x| x.max(seen_max))),
+            MaxMode::First => self.first_max.or(captured_max),
+            MaxMode::Fixed(max) => Some(max),
+        }
+    }
+
+    /// Convert a captured value to the value to be recorded, i.e., apply
+    /// `Options::invert`. Returns `None` if the maximum value is unknown.
+    fn stored_value(&self, value: f64, captured_max: Option<f64>) -> Option<f64> {
+        if self.options.invert {
+            self.peek_max(value, captured_max).map(|max| max - value)
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Get the samples with `Options::reset_mode` (if cumulative) and
+    /// `Options::median` applied. `samples` retains the raw values.
+    pub fn filtered_samples(&self) -> Cow<'_, VecDeque<Sample>> {
+        let mut samples = Cow::Borrowed(&self.samples);
+        if self.options.reset_mode == ResetMode::Cumulative {
+            samples = Cow::Owned(accumulate_resets(&samples));
+        }
+        match self.options.median {
+            Some(window) if window > 1 => Cow::Owned(median_filter(&samples, window)),
+            _ => samples,
+        }
+    }
+
+    /// Calculate the current progress from `filtered_samples`. Returns `None`
+    /// if there are not enough samples to do that.
+    pub fn progress(&self) -> Option<Progress> {
+        let samples = self.filtered_samples();
+        let start = self.segment_start(&samples);
+
+        if samples.len() - start < 2 {
+            return None;
+        }
+
+        let last = samples.back()?;
+        let steady_start = self.steady_start(&samples, start);
+
+        if samples.len().saturating_sub(steady_start) < 2
+            || last.instant == samples[steady_start].instant
+        {
+            return Some(Progress {
+                value: last.value,
+                max: last.max,
+                rate: f64::NAN,
+                recent_rate: None,
+                eta: None,
+                eta_range: None,
+                elapsed: 0.0,
+                warming_up: true,
+            });
+        }
+
+        let first = &samples[steady_start];
+        let elapsed = last.instant.duration_since(first.instant).as_secs_f64();
+        let rate = (last.value - first.value) / elapsed;
+
+        let smoothed_rate = self.options.smooth.and_then(|smoothing| {
+            let data = samples.range(steady_start..).map(|s| {
+                (
+                    s.instant.duration_since(first.instant).as_secs_f64(),
+                    s.value,
+                )
+            });
+            smoothing
+                .apply(analyze_rate(data))
+                .last()
+                .map(|&(_, rate)| rate)
+        });
+
+        let (eta, eta_range) = match (last.max, smoothed_rate) {
+            (None, _) => (None, None),
+            (Some(max), Some(smoothed_rate)) => (
+                Some((max - last.value) / smoothed_rate)
+                    .filter(|eta| eta.is_finite() && *eta >= 0.0),
+                None,
+            ),
+            (Some(max), None) => {
+                let window_start = self.options.eta_window.map_or(steady_start, |window| {
+                    let since = last.instant.checked_sub(window);
+                    (steady_start..samples.len() - 2)
+                        .find(|&i| since.map_or(true, |since| samples[i].instant >= since))
+                        .unwrap_or(samples.len() - 2)
+                });
+                let data: Vec<_> = samples
+                    .range(window_start..)
+                    .map(|s| {
+                        (
+                            s.instant.duration_since(first.instant).as_secs_f64(),
+                            s.value,
+                        )
+                    })
+                    .collect();
+                let estimate = eta::estimate(&data, last.value, max);
+                (estimate.map(|e| e.eta), estimate.map(|e| e.range))
+            }
+        };
+
+        let recent = &samples[samples
+            .len()
+            .saturating_sub(RECENT_RATE_SAMPLES)
+            .max(steady_start)];
+        let recent_rate = smoothed_rate
+            .or_else(|| {
+                Some(
+                    (last.value - recent.value)
+                        / last.instant.duration_since(recent.instant).as_secs_f64(),
+                )
+            })
+            .filter(|rate| rate.is_finite());
+
+        Some(Progress {
+            value: last.value,
+            max: last.max,
+            rate,
+            recent_rate,
+            eta,
+            eta_range,
+            elapsed,
+            warming_up: false,
+        })
+    }
+
+    /// Get the highest rate between consecutive samples.
+    pub fn peak_rate(&self) -> Option<f64> {
+        let samples = self.filtered_samples();
+        samples
+            .iter()
+            .zip(samples.iter().skip(1))
+            .map(|(s0, s1)| {
+                (s1.value - s0.value) / s1.instant.duration_since(s0.instant).as_secs_f64()
+            })
+            .filter(|rate| rate.is_finite())
+            .fold(None, |peak: Option<f64>, rate| {
+                Some(peak.map_or(rate, |p| p.max(rate)))
+            })
+    }
+
+    /// Get the number of captured strings that couldn't be parsed as values
+    /// and the last of them.
+    pub fn parse_errors(&self) -> Option<(usize, &str)> {
+        let last = self.last_parse_error.as_deref()?;
+        Some((self.num_parse_errors, last))
+    }
+
+    /// Get how long the value has stayed the same as of `now` if it's at least
+    /// `Options::stall_timeout`.
+    pub fn stalled_for(&self, now: Instant) -> Option<Duration> {
+        let timeout = self.options.stall_timeout?;
+        let last = self.samples.back()?;
+        let since = self
+            .samples
+            .iter()
+            .rev()
+            .take_while(|s| s.value == last.value)
+            .last()?
+            .instant;
+        Some(now.saturating_duration_since(since)).filter(|&stalled| stalled >= timeout)
+    }
+
+    /// Get the instant of the first sample the rate is calculated from, i.e.,
+    /// the end of the warmup period. Returns `None` if it hasn't elapsed yet.
+    pub fn steady_since(&self) -> Option<Instant> {
+        let samples = self.filtered_samples();
+        let start = self.segment_start(&samples);
+        samples
+            .get(self.steady_start(&samples, start))
+            .map(|sample| sample.instant)
+    }
+
+    /// Get the index of the first sample of the latest segment. Only the
+    /// latest segment counts in `ResetMode::Segment`.
+    fn segment_start(&self, samples: &VecDeque<Sample>) -> usize {
+        if self.options.reset_mode == ResetMode::Segment {
+            (1..samples.len())
+                .rev()
+                .find(|&i| samples[i].value < samples[i - 1].value)
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    /// Get the index of the first sample past the warmup period of the
+    /// segment starting at `start`. Returns `samples.len()` if the warmup
+    /// period hasn't elapsed yet.
+    fn steady_start(&self, samples: &VecDeque<Sample>, start: usize) -> usize {
+        match self.options.warmup {
+            None => start,
+            Some(Warmup::Samples(count)) => (start + count).min(samples.len()),
+            Some(Warmup::Duration(duration)) => {
+                let end = samples.get(start).map(|first| first.instant + duration);
+                (start..samples.len())
+                    .find(|&i| end.map_or(false, |end| samples[i].instant >= end))
+                    .unwrap_or(samples.len())
+            }
+        }
+    }
+}
+
+/// Replace each sample's value with the median of the values of the sample and
+/// up to `window - 1` preceding ones.
+fn median_filter(samples: &VecDeque<Sample>, window: usize) -> VecDeque<Sample> {
+    let values: Vec<f64> = samples.iter().map(|s| s.value).collect();
+    let mut sorted = Vec::with_capacity(window);
+
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| {
+            sorted.clear();
+            sorted.extend_from_slice(&values[(i + 1).saturating_sub(window)..=i]);
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mid = sorted.len() / 2;
+            let value = if sorted.len() % 2 == 0 {
+                (sorted[mid - 1] + sorted[mid]) / 2.0
+            } else {
+                sorted[mid]
+            };
+
+            Sample {
+                value,
+                ..sample.clone()
+            }
+        })
+        .collect()
+}
+
+/// Extract `(value, max)` pairs from the JSON documents in `text`, which is
+/// treated as a sequence of single-line documents (JSON Lines) or, failing
+/// that, a single document. Also returns the byte offset of the document
+/// that yielded each pair.
+fn extract_json(
+    text: &str,
+    pointers: &JsonPointers,
+    parse: impl Fn(&str) -> Option<f64>,
+) -> (Vec<(f64, Option<f64>)>, Vec<usize>) {
+    // Numbers are sometimes emitted as strings
+    let number = |value: &json::Value| match value {
+        json::Value::Number(x) => Some(*x),
+        json::Value::String(s) => parse(s.trim()),
+        _ => None,
+    };
+    let extract = |doc: &json::Value| {
+        let value = number(doc.pointer(&pointers.value)?)?;
+        let max = pointers.max.as_ref().and_then(|p| number(doc.pointer(p)?));
+        Some((value, max))
+    };
+
+    let mut docs: Vec<_> = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(start, line)| Some((start, json::Value::parse(line)?)))
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    if docs.is_empty() {
+        let start = text.len() - text.trim_start().len();
+        docs.extend(json::Value::parse(text).map(|doc| (start, doc)));
+    }
+
+    docs.iter()
+        .filter_map(|(start, doc)| Some((extract(doc)?, *start)))
+        .unzip()
+}
+
+/// Extract `(value, max)` pairs from the lines of `text` containing the value
+/// key. Also returns the byte offset of the line that yielded each pair.
+fn extract_kv(
+    text: &str,
+    keys: &logfmt::Keys,
+    parse: impl Fn(&str) -> Option<f64>,
+) -> (Vec<(f64, Option<f64>)>, Vec<usize>) {
+    let mut offset = 0;
+    let mut pairs = Vec::new();
+    let mut offsets = Vec::new();
+
+    for line in text.split_inclusive('\n') {
+        let fields = logfmt::parse(line);
+        let get = |key: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .and_then(|(_, v)| parse(v))
+        };
+        if let Some(value) = get(&keys.value) {
+            let max = keys.max.as_deref().and_then(get);
+            pairs.push((value, max));
+            offsets.push(offset);
+        }
+        offset += line.len();
+    }
+
+    (pairs, offsets)
+}
+
+/// Check if an autodetected number is likely to be a quantity and not, e.g.,
+/// a part of a version number or an IP address (`1.2.3`, `10.0.0.1`).
+fn is_plausible_number(text: &str, mat: &regex::Match<'_>) -> bool {
+    let dotted = |s: &str| s.starts_with('.') && s[1..].starts_with(|c: char| c.is_ascii_digit());
+    let before: String = text[..mat.start()].chars().rev().take(2).collect();
+    !dotted(&text[mat.end()..]) && !dotted(&before)
+}
+
+/// Get the line in `text` containing the byte offset `i`.
+fn line_at(text: &str, i: usize) -> &str {
+    let start = text[..i].rfind('\n').map_or(0, |k| k + 1);
+    let end = text[i..].find('\n').map_or(text.len(), |k| i + k);
+    text[start..end].trim_end_matches('\r')
+}
+
+/// Offset the values (and maximum values) following each decrease by the
+/// value before the decrease.
+fn accumulate_resets(samples: &VecDeque<Sample>) -> VecDeque<Sample> {
+    let mut offset = 0.0;
+    let mut last_value: Option<f64> = None;
+
+    samples
+        .iter()
+        .map(|sample| {
+            if let Some(last_value) = last_value {
+                if sample.value < last_value {
+                    offset += last_value;
+                }
+            }
+            last_value = Some(sample.value);
+
+            Sample {
+                value: sample.value + offset,
+                max: sample.max.map(|max| max + offset),
+                ..sample.clone()
+            }
+        })
+        .collect()
+}
+
+/// Calculate the rate of change from `(time, value)` pairs in chronological
+/// order.
+///
+/// Values often change less frequently than they are sampled. Thus, a rate is
+/// only calculated between the first samples of consecutive runs of equal
+/// values and is attributed to the later one, which is when the change was
+/// first observed. The first rate is discarded because the run preceding it
+/// may extend beyond the given samples.
+pub fn analyze_rate(data: impl Iterator<Item = (f64, f64)>) -> impl Iterator<Item = (f64, f64)> {
+    data.scan(None, |st, (t, v)| {
+        if let Some((last_t, last_v)) = *st {
+            if v == last_v {
+                Some(None)
+            } else if t <= last_t {
+                // Observed at once with the previous sample
+                *st = Some((last_t, v));
+                Some(None)
+            } else {
+                *st = Some((t, v));
+                Some(Some((t, (v - last_v) / (t - last_t))))
+            }
+        } else {
+            *st = Some((t, v));
+            Some(None)
+        }
+    })
+    .flatten()
+    .skip(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+    fn test_analyzer() -> Analyzer {
+        Analyzer::new(Options::default())
+    }
+
+    /// Push samples taken a second apart, ending now.
+    fn push_samples(analyzer: &mut Analyzer, values: &[f64]) {
+        let now = Instant::now();
+        for (i, &value) in values.iter().enumerate() {
+            analyzer.samples.push_back(Sample {
+                instant: now - Duration::from_secs((values.len() - 1 - i) as u64),
+                time: SystemTime::now(),
+                value,
+                max: Some(100.0),
+                line: None,
+                series: Vec::new(),
+            });
+        }
+    }
+
+    fn exited_output(stdout: &str) -> CmdOutput {
+        CmdOutput {
+            status: Some(ExitStatus::from_raw(0)),
+            stdout: stdout.to_owned(),
+            stderr: String::new(),
+            stdout_raw: stdout.as_bytes().to_vec(),
+            stderr_raw: Vec::new(),
+            stdout_offset: 0,
+            stdout_truncated: false,
+            stderr_truncated: false,
+            stdout_len: stdout.len(),
+            stdout_num_lines: stdout.matches('\n').count(),
+            lines: Vec::new(),
+            started: Instant::now(),
+            start_time: SystemTime::now(),
+            duration: Some(Duration::from_secs(0)),
+            success: Some(true),
+        }
+    }
+
+    #[test]
+    fn process_output_min_sample_interval() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.min_sample_interval = Duration::from_secs(3600);
+
+        let mut output = exited_output("1/10\n");
+        output.status = None;
+        analyzer.process_output(&output);
+        output.stdout += "1/10\n";
+        analyzer.process_output(&output);
+        assert_eq!(analyzer.samples.len(), 1);
+
+        output.stdout += "2/10\n";
+        analyzer.process_output(&output);
+        assert_eq!(analyzer.samples.len(), 2);
+
+        // The final value is recorded regardless
+        output.stdout += "2/10\n";
+        output.status = exited_output("").status;
+        analyzer.process_output(&output);
+        assert_eq!(analyzer.samples.len(), 3);
+    }
+
+    #[test]
+    fn process_output_autodetect_skips_version_numbers() {
+        let mut analyzer = test_analyzer();
+        analyzer.process_output(&exited_output("tool v1.2.3 at 10.0.0.1: 42 done\n"));
+        assert_eq!(analyzer.samples.len(), 1);
+        assert_eq!(analyzer.samples[0].value, 42.0);
+    }
+
+    #[test]
+    fn process_output_matched_line() {
+        let mut analyzer = test_analyzer();
+        analyzer.process_output(&exited_output("starting\r\ncopied 3/10 files\r\ndone\n"));
+        assert_eq!(
+            analyzer.samples[0].line.as_deref(),
+            Some("copied 3/10 files")
+        );
+
+        analyzer.process_output(&exited_output("elapsed: 42\n"));
+        assert_eq!(analyzer.samples[1].line.as_deref(), Some("elapsed: 42"));
+    }
+
+    #[test]
+    fn process_output_meter() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.meter = Some(Meter::Lines);
+
+        let mut output = exited_output("1/10\n2/10\n3/10\n");
+        output.status = None;
+        analyzer.process_output(&output);
+        analyzer.process_output(&exited_output("1/10\n2/10\n3/10\n4/10\n"));
+        analyzer.process_output(&exited_output("1/10\n"));
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [3.0, 4.0, 1.0]);
+
+        let mut analyzer = test_analyzer();
+        analyzer.options.meter = Some(Meter::Bytes);
+        analyzer.options.cumulative = true;
+
+        analyzer.process_output(&exited_output("abc\n"));
+        analyzer.process_output(&exited_output("de\n"));
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [4.0, 7.0]);
+    }
+
+    #[test]
+    fn process_output_invert() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.invert = true;
+
+        analyzer.process_output(&exited_output("80/100 left\n"));
+        analyzer.process_output(&exited_output("30/100 left\n"));
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [20.0, 70.0]);
+
+        // The maximum value is unknown
+        analyzer.process_output(&exited_output("10 left\n"));
+        assert_eq!(analyzer.samples.len(), 2);
+
+        let mut analyzer = test_analyzer();
+        analyzer.options.invert = true;
+        analyzer.options.max_mode = MaxMode::Seen;
+
+        analyzer.process_output(&exited_output("50 left\n"));
+        analyzer.process_output(&exited_output("20 left\n"));
+        let values: Vec<_> = analyzer.samples.iter().map(|s| (s.value, s.max)).collect();
+        assert_eq!(values, [(0.0, Some(50.0)), (30.0, Some(50.0))]);
+    }
+
+    #[test]
+    fn process_output_pattern() {
+        let mut options = test_analyzer().options;
+        options.pattern = Some(parse_pattern(r"done (\d+) of (\d+)").unwrap());
+        let mut analyzer = Analyzer::new(options);
+
+        analyzer.process_output(&exited_output("3/4 ignored, done 5 of 20\n"));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (5.0, Some(20.0)));
+
+        assert!(analyzer.series_names().is_empty());
+
+        assert!(parse_pattern(r"\d+").is_err());
+        assert!(parse_pattern(r"(\d+").is_err());
+    }
+
+    #[test]
+    fn process_output_json() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.json = Some(JsonPointers {
+            value: "/progress/done".to_owned(),
+            max: Some("/progress/total".to_owned()),
+        });
+
+        // JSON Lines; the lines without the value are skipped
+        analyzer.process_output(&exited_output(
+            "{\"reason\": \"start\", \"count\": 9}\n\
+             {\"progress\": {\"done\": 3, \"total\": \"10\"}}\n",
+        ));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (3.0, Some(10.0)));
+        assert_eq!(
+            sample.line.as_deref(),
+            Some("{\"progress\": {\"done\": 3, \"total\": \"10\"}}")
+        );
+
+        // A pretty-printed document
+        analyzer.process_output(&exited_output(
+            "{\n  \"progress\": {\n    \"done\": 4,\n    \"total\": 10\n  }\n}\n",
+        ));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (4.0, Some(10.0)));
+
+        // Not JSON
+        analyzer.process_output(&exited_output("5/10\n"));
+        assert_eq!(analyzer.samples.len(), 2);
+    }
+
+    #[test]
+    fn process_output_kv() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.kv = Some("done=value total=max".parse().unwrap());
+
+        analyzer.process_output(&exited_output(
+            "starting total=1\nprogress done=1523 total=9000 phase=indexing\n",
+        ));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (1523.0, Some(9000.0)));
+        assert_eq!(
+            sample.line.as_deref(),
+            Some("progress done=1523 total=9000 phase=indexing")
+        );
+
+        analyzer.process_output(&exited_output("5/10 done\n"));
+        assert_eq!(analyzer.samples.len(), 1);
+    }
+
+    #[test]
+    fn parse_clock_duration_fields() {
+        let cases = [
+            ("1:02:03", Some(3723.0)),
+            ("01:02:03.5", Some(3723.5)),
+            ("100:00:00", Some(360000.0)),
+            ("02:03", Some(123.0)),
+            ("90:00", Some(5400.0)),
+            ("0:00.25", Some(0.25)),
+            ("42", None),
+            ("1:2:3:4", None),
+            ("1:-30", None),
+            ("1:+30", None),
+            ("-1:30", None),
+            ("1:-0:30", None),
+            ("1:30.", None),
+            ("1:.5", None),
+            ("1:1e3", None),
+            ("1:inf", None),
+            ("1::30", None),
+            (":30", None),
+            ("a:30", None),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_clock_duration(s), expected, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn parse_number_suffixes() {
+        let cases = [
+            ("42", Some(42.0)),
+            ("-0.5", Some(-0.5)),
+            ("1e3", Some(1000.0)),
+            ("3,214,000", Some(3214000.0)),
+            ("1,234.5", Some(1234.5)),
+            ("1.5G", Some(1.5e9)),
+            ("2 kB", Some(2000.0)),
+            ("12MiB", Some(12.0 * 1024.0 * 1024.0)),
+            ("1Ki", Some(1024.0)),
+            ("12,34", None),
+            ("1.5X", None),
+            ("MiB", None),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_number(s, false), expected, "{:?}", s);
+        }
+        assert_eq!(parse_number("1.5G", true), Some(1.5 * 1024f64.powi(3)));
+        assert_eq!(parse_number("1.5GiB", true), Some(1.5 * 1024f64.powi(3)));
+    }
+
+    #[test]
+    fn process_output_suffixed_values() {
+        let mut options = test_analyzer().options;
+        options.pattern = Some(parse_pattern(r"([\d.,]+\w*) of ([\d.,]+\w*)").unwrap());
+        let mut analyzer = Analyzer::new(options);
+
+        analyzer.process_output(&exited_output("copied 512MiB of 2GiB\n"));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!(
+            (sample.value, sample.max),
+            (0.5 * 1024f64.powi(3), Some(2.0 * 1024f64.powi(3)))
+        );
+
+        analyzer.process_output(&exited_output("1,500 of 3,000\n"));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (1500.0, Some(3000.0)));
+    }
+
+    #[test]
+    fn process_output_parse_errors() {
+        let mut options = test_analyzer().options;
+        options.pattern = Some(parse_pattern(r"done: (\S+)").unwrap());
+        let mut analyzer = Analyzer::new(options);
+
+        analyzer.process_output(&exited_output("done: 12\n"));
+        assert_eq!(analyzer.parse_errors(), None);
+
+        analyzer.process_output(&exited_output("done: n/a\n"));
+        analyzer.process_output(&exited_output("done: ???\n"));
+        assert_eq!(analyzer.parse_errors(), Some((2, "???")));
+        assert_eq!(analyzer.samples.len(), 1);
+    }
+
+    #[test]
+    fn process_output_percent() {
+        let mut options = test_analyzer().options;
+        options.pattern = Some(parse_pattern(PERCENT_PATTERN).unwrap());
+        options.max_mode = MaxMode::Fixed(100.0);
+        let mut analyzer = Analyzer::new(options);
+
+        for (stdout, value) in [
+            ("downloading 42%\n", 42.0),
+            ("[#####     ] 42.7 % of 1.2GB\n", 42.7),
+            ("step 3/10: 100%\n", 100.0),
+        ] {
+            analyzer.process_output(&exited_output(stdout));
+            let sample = analyzer.samples.back().unwrap();
+            assert_eq!(
+                (sample.value, sample.max),
+                (value, Some(100.0)),
+                "{:?}",
+                stdout
+            );
+        }
+    }
+
+    #[test]
+    fn parse_aggregate() {
+        assert_eq!("latest".parse(), Ok(Aggregate::Latest));
+        assert_eq!("last".parse(), Ok(Aggregate::Last));
+        assert_eq!("nth:3".parse(), Ok(Aggregate::Nth(3)));
+        assert_eq!("all".parse(), Ok(Aggregate::All));
+        assert!("nth:0".parse::<Aggregate>().is_err());
+        assert!("nth:x".parse::<Aggregate>().is_err());
+        assert!("second".parse::<Aggregate>().is_err());
+        assert_eq!(Aggregate::Nth(3).name(), "nth:3");
+
+        assert_eq!(parse_match_selection("first"), Ok(Aggregate::First));
+        assert_eq!(parse_match_selection("nth:2"), Ok(Aggregate::Nth(2)));
+        assert!(parse_match_selection("sum").is_err());
+        assert!(parse_match_selection("latest").is_err());
+    }
+
+    #[test]
+    fn aggregate_apply() {
+        let pairs = [(3.0, Some(10.0)), (7.0, Some(20.0)), (2.0, Some(30.0))];
+        assert_eq!(Aggregate::Sum.apply(&pairs), [(0, (12.0, Some(60.0)))]);
+        assert_eq!(Aggregate::Max.apply(&pairs), [(0, (7.0, Some(30.0)))]);
+        assert_eq!(Aggregate::Mean.apply(&pairs), [(0, (4.0, Some(20.0)))]);
+        assert_eq!(Aggregate::Last.apply(&pairs), [(2, (2.0, Some(30.0)))]);
+        assert_eq!(Aggregate::Nth(2).apply(&pairs), [(1, (7.0, Some(20.0)))]);
+        assert_eq!(Aggregate::All.apply(&pairs).len(), 3);
+
+        for aggregate in [
+            Aggregate::Sum,
+            Aggregate::Max,
+            Aggregate::Mean,
+            Aggregate::Last,
+        ] {
+            assert!(aggregate.apply(&[]).is_empty(), "{:?}", aggregate);
+        }
+    }
+
+    #[test]
+    fn aggregate_apply_partial_maxes() {
+        // Maximum values are combined only from the matches that have one
+        let pairs = [(3.0, Some(10.0)), (7.0, None), (2.0, Some(30.0))];
+        assert_eq!(Aggregate::Sum.apply(&pairs), [(0, (12.0, Some(40.0)))]);
+        assert_eq!(Aggregate::Max.apply(&pairs), [(0, (7.0, Some(30.0)))]);
+        assert_eq!(Aggregate::Mean.apply(&pairs), [(0, (4.0, Some(20.0)))]);
+        assert_eq!(Aggregate::Last.apply(&pairs), [(2, (2.0, Some(30.0)))]);
+
+        let pairs = [(3.0, None), (-5.0, None)];
+        assert_eq!(Aggregate::Sum.apply(&pairs), [(0, (-2.0, None))]);
+        assert_eq!(Aggregate::Max.apply(&pairs), [(0, (3.0, None))]);
+        assert_eq!(Aggregate::Mean.apply(&pairs), [(0, (-1.0, None))]);
+        assert_eq!(Aggregate::Last.apply(&pairs), [(1, (-5.0, None))]);
+    }
+
+    #[test]
+    fn process_output_match_selection() {
+        let output = exited_output("a.txt 3/10\nb.txt 7/20\ntotal 10/30\n");
+        for (aggregate, value, line) in [
+            (Aggregate::First, 3.0, "a.txt 3/10"),
+            (Aggregate::Last, 10.0, "total 10/30"),
+            (Aggregate::Nth(2), 7.0, "b.txt 7/20"),
+        ] {
+            let mut analyzer = test_analyzer();
+            analyzer.options.aggregate = aggregate;
+            analyzer.process_output(&output);
+            let sample = analyzer.samples.back().unwrap();
+            assert_eq!(sample.value, value, "{:?}", aggregate);
+            assert_eq!(sample.line.as_deref(), Some(line), "{:?}", aggregate);
+        }
+
+        let mut analyzer = test_analyzer();
+        analyzer.options.aggregate = Aggregate::Nth(4);
+        analyzer.process_output(&output);
+        assert!(analyzer.samples.is_empty());
+    }
+
+    #[test]
+    fn process_output_streamed_lines() {
+        let mut analyzer = test_analyzer();
+        let mut output = exited_output("1/3\n2/3\n3/3\n");
+        output.status = None;
+        analyzer.process_output(&output);
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!(sample.value, 3.0);
+        assert_eq!(sample.line.as_deref(), Some("3/3"));
+
+        // Nothing is left when the command exits
+        output.status = exited_output("").status;
+        analyzer.process_output(&output);
+        assert_eq!(analyzer.samples.len(), 1);
+
+        // Only the complete lines of a new run are processed while running
+        let mut analyzer = test_analyzer();
+        output.stdout = "1/3\n2/3\n3/".to_owned();
+        output.status = None;
+        analyzer.process_output(&output);
+        assert_eq!(analyzer.samples.back().unwrap().value, 2.0);
+
+        // `First` waits for the entire run and takes its first match
+        let mut analyzer = test_analyzer();
+        analyzer.options.aggregate = Aggregate::First;
+        analyzer.process_output(&output);
+        assert!(analyzer.samples.is_empty());
+        output.stdout = "1/3\n2/3\n3/3\n".to_owned();
+        output.status = exited_output("").status;
+        analyzer.process_output(&output);
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [1.0]);
+    }
+
+    #[test]
+    fn process_output_all_matches() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.aggregate = Aggregate::All;
+        analyzer.process_output(&exited_output("1/10\n2/10 3/10\n4/10\n"));
+
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [1.0, 2.0, 3.0, 4.0]);
+        let lines: Vec<_> = analyzer.samples.iter().map(|s| s.line.as_deref()).collect();
+        assert_eq!(
+            lines,
+            [
+                Some("1/10"),
+                Some("2/10 3/10"),
+                Some("2/10 3/10"),
+                Some("4/10")
+            ]
+        );
+
+        // Observed at once, so there's no rate yet
+        assert!(analyzer.progress().unwrap().warming_up);
+        assert_eq!(analyzer.peak_rate(), None);
+    }
+
+    #[test]
+    fn process_output_named_series() {
+        let mut options = test_analyzer().options;
+        options.pattern = Some(
+            parse_pattern(
+                r"(?P<done>\d+) of (\d+)(?:, (?P<errors>\d+) errors)?(, (?P<skipped>\d+))?",
+            )
+            .unwrap(),
+        );
+        let mut analyzer = Analyzer::new(options);
+        assert_eq!(analyzer.value_name(), Some("done"));
+        assert_eq!(analyzer.series_names(), ["errors", "skipped"]);
+
+        analyzer.process_output(&exited_output("5 of 20, 2 errors\n"));
+        let sample = analyzer.samples.back().unwrap();
+        assert_eq!((sample.value, sample.max), (5.0, Some(20.0)));
+        assert_eq!(sample.series, [Some(2.0), None]);
+    }
+
+    #[test]
+    fn process_output_reject_outliers() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.reject_outliers = true;
+
+        for value in &[10, 20, 30, 100000, 40] {
+            analyzer.process_output(&exited_output(&format!("{}/1000\n", value)));
+        }
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [10.0, 20.0, 30.0, 40.0]);
+
+        // A persistent shift is eventually accepted
+        for _ in 0..4 {
+            analyzer.process_output(&exited_output("100000/1000\n"));
+        }
+        assert_eq!(analyzer.samples.back().unwrap().value, 100000.0);
+    }
+
+    #[test]
+    fn process_output_directives() {
+        let mut analyzer = test_analyzer();
+        analyzer.process_output(&exited_output(
+            "#pvfilt interval=2s pattern=step=(\\d+) unknown=1\nstep=5 of 9/10\n",
+        ));
+        assert_eq!(
+            analyzer.take_requested_interval(),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(analyzer.take_requested_interval(), None);
+        assert_eq!(analyzer.samples.len(), 1);
+        assert_eq!(analyzer.samples[0].value, 5.0);
+        assert_eq!(analyzer.samples[0].max, None);
+        assert!(analyzer.diagnostics.is_empty());
+
+        analyzer.process_output(&exited_output("#pvfilt pattern=(\nstep=6\n"));
+        assert_eq!(analyzer.diagnostics.len(), 1);
+        assert_eq!(analyzer.samples.back().unwrap().value, 6.0);
+    }
+
+    #[test]
+    fn process_output_overflowing_value() {
+        let huge = "9".repeat(400);
+
+        let mut analyzer = test_analyzer();
+        analyzer.process_output(&exited_output(&format!("{}/100\n", huge)));
+        assert!(analyzer.samples.is_empty());
+        assert_eq!(analyzer.diagnostics.len(), 1);
+
+        analyzer.process_output(&exited_output("42/100\n"));
+        assert_eq!(analyzer.samples.len(), 1);
+        assert_eq!(analyzer.samples[0].value, 42.0);
+    }
+
+    #[test]
+    fn process_output_overflowing_max() {
+        let huge = "9".repeat(400);
+
+        let mut analyzer = test_analyzer();
+        analyzer.process_output(&exited_output(&format!("42/{}\n", huge)));
+        assert_eq!(analyzer.samples.len(), 1);
+        assert_eq!(analyzer.samples[0].value, 42.0);
+        assert_eq!(analyzer.samples[0].max, None);
+        assert_eq!(analyzer.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn window_discards_old_samples() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.window = Some(Duration::from_millis(1500));
+        push_samples(&mut analyzer, &[10.0, 20.0, 30.0, 40.0, 50.0]);
+
+        analyzer.process_output(&exited_output("60/100\n"));
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        assert_eq!(values, [40.0, 50.0, 60.0]);
+    }
+
+    #[test]
+    fn progress_warmup_samples() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.warmup = Some(Warmup::Samples(2));
+
+        push_samples(&mut analyzer, &[0.0, 1.0, 10.0]);
+        let progress = analyzer.progress().unwrap();
+        assert!(progress.warming_up);
+        assert!(progress.rate.is_nan());
+        assert_eq!(progress.eta, None);
+
+        push_samples(&mut analyzer, &[20.0]);
+        let progress = analyzer.progress().unwrap();
+        assert!(!progress.warming_up);
+        assert_eq!(progress.value, 20.0);
+        assert!(progress.rate > 0.0);
+    }
+
+    #[test]
+    fn progress_warmup_duration() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.warmup = Some(Warmup::Duration(Duration::from_secs(2)));
+
+        push_samples(&mut analyzer, &[0.0, 1.0, 10.0, 20.0, 30.0]);
+        let progress = analyzer.progress().unwrap();
+        assert!(!progress.warming_up);
+        assert!((progress.rate - 10.0).abs() < 0.1);
+        assert_eq!(analyzer.steady_since(), Some(analyzer.samples[2].instant));
+    }
+
+    #[test]
+    fn stalled_for() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.stall_timeout = Some(Duration::from_secs(2));
+
+        push_samples(&mut analyzer, &[0.0, 1.0, 1.0, 1.0]);
+        let now = analyzer.samples.back().unwrap().instant;
+        assert_eq!(analyzer.stalled_for(now), Some(Duration::from_secs(2)));
+        assert_eq!(analyzer.stalled_for(now - Duration::from_secs(1)), None);
+
+        push_samples(&mut analyzer, &[2.0]);
+        assert_eq!(analyzer.stalled_for(now), None);
+    }
+
+    fn values(samples: &VecDeque<Sample>) -> Vec<f64> {
+        samples.iter().map(|s| s.value).collect()
+    }
+
+    #[test]
+    fn accumulate_resets_offsets() {
+        let mut analyzer = test_analyzer();
+
+        // A single reset
+        push_samples(&mut analyzer, &[10.0, 20.0, 5.0, 15.0]);
+        let samples = accumulate_resets(&analyzer.samples);
+        assert_eq!(values(&samples), [10.0, 20.0, 25.0, 35.0]);
+        assert_eq!(samples[2].max, Some(120.0));
+
+        // Repeated resets
+        analyzer.samples.clear();
+        push_samples(&mut analyzer, &[10.0, 0.0, 5.0, 0.0, 3.0, 3.0]);
+        let samples = accumulate_resets(&analyzer.samples);
+        assert_eq!(values(&samples), [10.0, 10.0, 15.0, 15.0, 18.0, 18.0]);
+
+        // A reset on the last sample
+        analyzer.samples.clear();
+        push_samples(&mut analyzer, &[10.0, 20.0, 1.0]);
+        let samples = accumulate_resets(&analyzer.samples);
+        assert_eq!(values(&samples), [10.0, 20.0, 21.0]);
+    }
+
+    #[test]
+    fn median_filter_windows() {
+        let mut analyzer = test_analyzer();
+        push_samples(&mut analyzer, &[1.0, 100.0, 3.0, 4.0, -50.0, 6.0]);
+
+        let samples = median_filter(&analyzer.samples, 1);
+        assert_eq!(values(&samples), values(&analyzer.samples));
+
+        // The spikes are removed
+        let samples = median_filter(&analyzer.samples, 3);
+        assert_eq!(values(&samples), [1.0, 50.5, 3.0, 4.0, 3.0, 4.0]);
+        assert_eq!(samples[1].instant, analyzer.samples[1].instant);
+
+        // An even window averages the two middle values
+        let samples = median_filter(&analyzer.samples, 2);
+        assert_eq!(values(&samples), [1.0, 50.5, 51.5, 3.5, -23.0, -22.0]);
+
+        // A window larger than the samples covers all the preceding ones
+        let samples = median_filter(&analyzer.samples, 10);
+        assert_eq!(values(&samples), [1.0, 50.5, 3.0, 3.5, 3.0, 3.5]);
+
+        assert!(median_filter(&VecDeque::new(), 3).is_empty());
+    }
+
+    #[test]
+    fn segment_start_latest_reset() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.reset_mode = ResetMode::Segment;
+
+        push_samples(&mut analyzer, &[10.0, 20.0, 30.0]);
+        assert_eq!(analyzer.segment_start(&analyzer.samples), 0);
+
+        // A single reset
+        analyzer.samples.clear();
+        push_samples(&mut analyzer, &[10.0, 20.0, 5.0, 15.0]);
+        assert_eq!(analyzer.segment_start(&analyzer.samples), 2);
+
+        // Repeated resets
+        analyzer.samples.clear();
+        push_samples(&mut analyzer, &[10.0, 0.0, 5.0, 0.0, 3.0, 3.0]);
+        assert_eq!(analyzer.segment_start(&analyzer.samples), 3);
+
+        // A reset on the last sample
+        analyzer.samples.clear();
+        push_samples(&mut analyzer, &[10.0, 20.0, 1.0]);
+        assert_eq!(analyzer.segment_start(&analyzer.samples), 2);
+
+        analyzer.options.reset_mode = ResetMode::None;
+        assert_eq!(analyzer.segment_start(&analyzer.samples), 0);
+    }
+
+    #[test]
+    fn parse_warmup() {
+        assert_eq!("5".parse(), Ok(Warmup::Samples(5)));
+        assert_eq!("30s".parse(), Ok(Warmup::Duration(Duration::from_secs(30))));
+        assert!("soon".parse::<Warmup>().is_err());
+    }
+
+    #[test]
+    fn parse_smoothing() {
+        assert_eq!("sma:5".parse(), Ok(Smoothing::MovingAverage(5)));
+        assert_eq!("ewma:0.3".parse(), Ok(Smoothing::Ewma(0.3)));
+        assert!("sma:0".parse::<Smoothing>().is_err());
+        assert!("ewma:1.5".parse::<Smoothing>().is_err());
+        assert!("ewma".parse::<Smoothing>().is_err());
+    }
+
+    #[test]
+    fn smoothing_apply() {
+        let data = [(1.0, 10.0), (2.0, 20.0), (3.0, 0.0), (4.0, 30.0)];
+        assert_eq!(
+            Smoothing::MovingAverage(2).apply(data.iter().cloned()),
+            vec![(1.0, 10.0), (2.0, 15.0), (3.0, 10.0), (4.0, 15.0)]
+        );
+        assert_eq!(
+            Smoothing::Ewma(0.5).apply(data.iter().cloned()),
+            vec![(1.0, 10.0), (2.0, 15.0), (3.0, 7.5), (4.0, 18.75)]
+        );
+    }
+
+    #[test]
+    fn progress_smoothed_eta() {
+        let mut analyzer = test_analyzer();
+        analyzer.options.smooth = Some(Smoothing::MovingAverage(2));
+
+        push_samples(&mut analyzer, &[0.0, 10.0, 20.0, 50.0, 80.0]);
+        analyzer.samples.back_mut().unwrap().max = Some(140.0);
+        let progress = analyzer.progress().unwrap();
+        // The average rate is 20/s, but the last two rates are 30/s
+        assert!((progress.rate - 20.0).abs() < 0.1);
+        assert!((progress.recent_rate.unwrap() - 30.0).abs() < 0.1);
+        assert!((progress.eta.unwrap() - 2.0).abs() < 0.1);
+    }
+
+    fn rates(data: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        analyze_rate(data.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn analyze_rate_too_few() {
+        assert_eq!(rates(&[]), []);
+        assert_eq!(rates(&[(0.0, 1.0)]), []);
+        assert_eq!(rates(&[(0.0, 1.0), (1.0, 2.0)]), []);
+    }
+
+    #[test]
+    fn analyze_rate_linear() {
+        assert_eq!(
+            rates(&[(0.0, 0.0), (1.0, 2.0), (2.0, 4.0), (4.0, 8.0)]),
+            [(2.0, 2.0), (4.0, 2.0)]
+        );
+    }
+
+    #[test]
+    fn analyze_rate_constant_stretches() {
+        // Updated every 3 time units, sampled every time unit
+        assert_eq!(
+            rates(&[
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (2.0, 3.0),
+                (3.0, 3.0),
+                (4.0, 3.0),
+                (5.0, 6.0),
+                (6.0, 6.0),
+                (7.0, 6.0),
+                (8.0, 9.0),
+                (9.0, 9.0),
+            ]),
+            [(5.0, 1.0), (8.0, 1.0)]
+        );
+        assert_eq!(rates(&[(0.0, 5.0), (1.0, 5.0), (2.0, 5.0)]), []);
+    }
+
+    #[test]
+    fn analyze_rate_simultaneous() {
+        assert_eq!(
+            rates(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (2.0, 3.0), (3.0, 4.0)]),
+            [(2.0, 1.0), (3.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn analyze_rate_non_monotonic() {
+        assert_eq!(
+            rates(&[(0.0, 0.0), (1.0, 4.0), (2.0, 2.0), (3.0, 2.0), (4.0, 6.0)]),
+            [(2.0, -2.0), (4.0, 2.0)]
+        );
+    }
 }
@@ -0,0 +1,121 @@
+//! Estimates the time to completion by fitting a line to the samples, which is
+//! far less sensitive to noise than the slope between two of them.
+
+/// The number of standard errors covered by each side of the confidence
+/// interval, which amounts to about 95%.
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// A least-squares regression line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    /// The change per second.
+    pub slope: f64,
+    /// The standard error of `slope`. NaN if fitted to only two points.
+    pub slope_error: f64,
+}
+
+/// Fit a line to `(time, value)` pairs. Returns `None` if there are fewer than
+/// two distinct times.
+pub fn fit(data: &[(f64, f64)]) -> Option<Fit> {
+    let n = data.len() as f64;
+    let mean_t = data.iter().map(|&(t, _)| t).sum::<f64>() / n;
+    let mean_v = data.iter().map(|&(_, v)| v).sum::<f64>() / n;
+
+    let sxx: f64 = data.iter().map(|&(t, _)| (t - mean_t).powi(2)).sum();
+    let sxy: f64 = data.iter().map(|&(t, v)| (t - mean_t) * (v - mean_v)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let slope_error = if data.len() > 2 {
+        let sse: f64 = data
+            .iter()
+            .map(|&(t, v)| (v - mean_v - slope * (t - mean_t)).powi(2))
+            .sum();
+        (sse / (n - 2.0) / sxx).sqrt()
+    } else {
+        f64::NAN
+    };
+
+    Some(Fit { slope, slope_error })
+}
+
+/// An estimated time to completion in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub eta: f64,
+    /// The ETAs at the bounds of the confidence interval of the rate, earliest
+    /// first. The latter is infinite if the rate may not be positive.
+    pub range: (f64, f64),
+}
+
+/// Estimate the time it takes to go from `value` to `max` at the rate fitted
+/// to `(time, value)` pairs. Returns `None` if the fitted rate doesn't head
+/// toward `max`.
+pub fn estimate(data: &[(f64, f64)], value: f64, max: f64) -> Option<Estimate> {
+    let fit = fit(data)?;
+    let remaining = max - value;
+    let eta = remaining / fit.slope;
+    if !(eta.is_finite() && eta >= 0.0) {
+        return None;
+    }
+
+    let margin = CONFIDENCE_Z * fit.slope_error;
+    let range = if margin.is_nan() {
+        (eta, eta)
+    } else if fit.slope - margin > 0.0 {
+        (
+            remaining / (fit.slope + margin),
+            remaining / (fit.slope - margin),
+        )
+    } else {
+        (remaining / (fit.slope + margin), f64::INFINITY)
+    };
+
+    Some(Estimate { eta, range })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_exact_line() {
+        let fit = fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]).unwrap();
+        assert!((fit.slope - 2.0).abs() < 1e-9);
+        assert!(fit.slope_error.abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_too_few() {
+        assert_eq!(fit(&[]), None);
+        assert_eq!(fit(&[(1.0, 1.0)]), None);
+        assert_eq!(fit(&[(1.0, 1.0), (1.0, 2.0)]), None);
+        assert!(fit(&[(0.0, 0.0), (1.0, 2.0)]).unwrap().slope_error.is_nan());
+    }
+
+    #[test]
+    fn estimate_noisy() {
+        let data: Vec<_> = (0..10)
+            .map(|i| {
+                (
+                    i as f64,
+                    i as f64 * 10.0 + if i % 2 == 0 { 3.0 } else { -3.0 },
+                )
+            })
+            .collect();
+        let estimate = estimate(&data, 90.0, 190.0).unwrap();
+        assert!((estimate.eta - 10.0).abs() < 1.0);
+        assert!(estimate.range.0 < estimate.eta && estimate.eta < estimate.range.1);
+        assert!(estimate.range.1.is_finite());
+    }
+
+    #[test]
+    fn estimate_receding() {
+        assert_eq!(
+            estimate(&[(0.0, 10.0), (1.0, 5.0), (2.0, 0.0)], 0.0, 100.0),
+            None
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! A minimal JSON parser for `Options::json`, which only needs to look values
+//! up by JSON pointers (RFC 6901).
+
+/// Nesting deeper than this is rejected so as not to overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// The members in the order of appearance.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parse a JSON text. Returns `None` if `s` is not exactly one value
+    /// optionally surrounded by whitespace.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parser = Parser {
+            s: s.as_bytes(),
+            i: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        Some(value).filter(|_| parser.i == parser.s.len())
+    }
+
+    /// Look up the value referenced by a JSON pointer, e.g., `/items/0/done`.
+    pub fn pointer(&self, pointer: &str) -> Option<&Self> {
+        if pointer.is_empty() {
+            return Some(self);
+        }
+        if !pointer.starts_with('/') {
+            return None;
+        }
+        pointer[1..].split('/').try_fold(self, |value, token| {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            match value {
+                Value::Object(members) => members
+                    .iter()
+                    .find(|(name, _)| *name == token)
+                    .map(|(_, value)| value),
+                Value::Array(elements) => elements.get(token.parse::<usize>().ok()?),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Check that `s` is a valid JSON pointer.
+pub fn parse_pointer(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.starts_with('/') {
+        Ok(s.to_owned())
+    } else {
+        Err(format!("invalid JSON pointer (expected `/KEY/...`): {}", s))
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.i) {
+            self.i += 1;
+        }
+    }
+
+    fn eat(&mut self, token: &[u8]) -> Option<()> {
+        if self.s[self.i..].starts_with(token) {
+            self.i += token.len();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match *self.s.get(self.i)? {
+            b'n' => self.eat(b"null").map(|_| Value::Null),
+            b't' => self.eat(b"true").map(|_| Value::Bool(true)),
+            b'f' => self.eat(b"false").map(|_| Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => {
+                self.i += 1;
+                let elements = self.list(b']', |parser| parser.value(depth + 1))?;
+                Some(Value::Array(elements))
+            }
+            b'{' => {
+                self.i += 1;
+                let members = self.list(b'}', |parser| {
+                    parser.skip_whitespace();
+                    let name = parser.string()?;
+                    parser.skip_whitespace();
+                    parser.eat(b":")?;
+                    Some((name, parser.value(depth + 1)?))
+                })?;
+                Some(Value::Object(members))
+            }
+            _ => self.number().map(Value::Number),
+        }
+    }
+
+    /// Parse comma-separated items up to `end`, which is consumed.
+    fn list<T>(&mut self, end: u8, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(&[end]).is_some() {
+            return Some(items);
+        }
+        loop {
+            items.push(item(self)?);
+            self.skip_whitespace();
+            if self.eat(&[end]).is_some() {
+                return Some(items);
+            }
+            self.eat(b",")?;
+        }
+    }
+
+    fn number(&mut self) -> Option<f64> {
+        let start = self.i;
+        while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.s.get(self.i) {
+            self.i += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.i])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat(b"\"")?;
+        let mut out = Vec::new();
+        loop {
+            match *self.s.get(self.i)? {
+                b'"' => {
+                    self.i += 1;
+                    return String::from_utf8(out).ok();
+                }
+                b'\\' => {
+                    let escaped = *self.s.get(self.i + 1)?;
+                    self.i += 2;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b => {
+                    out.push(b);
+                    self.i += 1;
+                }
+            }
+        }
+    }
+
+    /// Parse the hex digits following `\u`, including the low surrogate of a
+    /// surrogate pair.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if (0xd800..0xdc00).contains(&high) {
+            self.eat(b"\\u")?;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return None;
+            }
+            std::char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+        } else {
+            std::char::from_u32(high)
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = std::str::from_utf8(self.s.get(self.i..self.i + 4)?).ok()?;
+        self.i += 4;
+        u32::from_str_radix(digits, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let value =
+            Value::parse(r#" {"a/b": [1, {"done": 2.5e1}], "s": "x\"é😀", "n": null} "#).unwrap();
+        assert_eq!(value.pointer("/a~1b/1/done"), Some(&Value::Number(25.0)));
+        assert_eq!(value.pointer("/a~1b/0"), Some(&Value::Number(1.0)));
+        assert_eq!(
+            value.pointer("/s"),
+            Some(&Value::String("x\"é😀".to_owned()))
+        );
+        assert_eq!(value.pointer("/n"), Some(&Value::Null));
+        assert_eq!(value.pointer("/a~1b/2"), None);
+        assert_eq!(value.pointer("/missing"), None);
+        assert_eq!(value.pointer(""), Some(&value));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(Value::parse(""), None);
+        assert_eq!(Value::parse("{\"a\": 1"), None);
+        assert_eq!(Value::parse("[1, 2] 3"), None);
+        assert_eq!(Value::parse("progress: 3"), None);
+        assert_eq!(Value::parse(&"[".repeat(1000)), None);
+    }
+}
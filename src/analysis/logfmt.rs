@@ -0,0 +1,122 @@
+//! Tokenizes logfmt-style `key=value` pairs for `Options::kv`, e.g.,
+//! `progress done=1523 total=9000 msg="building index"`.
+use std::str::FromStr;
+
+/// Split `line` into `(key, value)` pairs. A value may be quoted with `"`, in
+/// which `\"` and `\\` are unescaped. A bare word (`progress` in the example)
+/// becomes a key with an empty value.
+pub fn parse(line: &str) -> Vec<(&str, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = &rest[key_end..];
+
+        let mut value = String::new();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            if let Some(quoted) = after_eq.strip_prefix('"') {
+                let mut chars = quoted.char_indices();
+                rest = "";
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            rest = &quoted[i + 1..];
+                            break;
+                        }
+                        '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                value.push_str(&after_eq[..end]);
+                rest = &after_eq[end..];
+            }
+        }
+
+        if !key.is_empty() {
+            pairs.push((key, value));
+        }
+        rest = rest.trim_start();
+    }
+
+    pairs
+}
+
+/// The keys of `Options::kv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys {
+    pub value: String,
+    pub max: Option<String>,
+}
+
+impl FromStr for Keys {
+    type Err = String;
+
+    /// Parse the keys assigned to the roles in the logfmt format, e.g.,
+    /// `done=value total=max`. `done` alone is short for `done=value`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut value, mut max) = (None, None);
+        for (key, role) in parse(s) {
+            match role.as_str() {
+                "value" | "" => value = Some(key.to_owned()),
+                "max" => max = Some(key.to_owned()),
+                _ => {
+                    return Err(format!(
+                        "unknown role `{}` (expected `value` or `max`): {}",
+                        role, s
+                    ))
+                }
+            }
+        }
+        Ok(Self {
+            value: value.ok_or_else(|| format!("no key is assigned to `value`: {}", s))?,
+            max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pairs() {
+        assert_eq!(
+            parse(r#"progress done=1523  total=9000 msg="a \"b\" c" empty= x"#),
+            [
+                ("progress", String::new()),
+                ("done", "1523".to_owned()),
+                ("total", "9000".to_owned()),
+                ("msg", "a \"b\" c".to_owned()),
+                ("empty", String::new()),
+                ("x", String::new()),
+            ]
+        );
+        assert!(parse("  ").is_empty());
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(
+            "done=value total=max".parse(),
+            Ok(Keys {
+                value: "done".to_owned(),
+                max: Some("total".to_owned())
+            })
+        );
+        assert_eq!(
+            "done".parse(),
+            Ok(Keys {
+                value: "done".to_owned(),
+                max: None
+            })
+        );
+        assert!("total=max".parse::<Keys>().is_err());
+        assert!("done=min".parse::<Keys>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Interprets ANSI escape sequences in a command's output.
+use std::convert::TryFrom;
+use tui::{
+    style::{Color, Modifier, Style},
+    widgets::Text,
+};
+
+/// The colors selected by SGR 30–37 and 40–47.
+const COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+];
+
+/// The colors selected by SGR 90–97 and 100–107.
+const BRIGHT_COLORS: [Color; 8] = [
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// Split `text` into spans styled according to the SGR (Select Graphic
+/// Rendition) sequences in it. `base` is the style in effect initially and
+/// after a reset. Other escape sequences are removed.
+pub fn to_styled_text(text: &str, base: Style) -> Vec<Text<'_>> {
+    let mut out = Vec::new();
+    let mut style = base;
+    let mut rest = text;
+
+    while let Some(i) = rest.find('\x1b') {
+        if i > 0 {
+            out.push(Text::styled(&rest[..i], style));
+        }
+
+        let (seq, after) = split_escape(&rest[i..]);
+        if seq.starts_with("\x1b[") && seq.ends_with('m') {
+            apply_sgr(&mut style, base, &seq[2..seq.len() - 1]);
+        }
+        rest = after;
+    }
+
+    if !rest.is_empty() {
+        out.push(Text::styled(rest, style));
+    }
+    out
+}
+
+/// Remove escape sequences from `text`.
+pub fn strip(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find('\x1b') {
+        out.push_str(&rest[..i]);
+        rest = split_escape(&rest[i..]).1;
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Split the escape sequence at the start of `s`, which must start with ESC,
+/// from the rest. An incomplete sequence extends to the end of `s`.
+fn split_escape(s: &str) -> (&str, &str) {
+    let len = match s[1..].chars().next() {
+        // CSI: parameter and intermediate bytes followed by a final byte
+        Some('[') => s.as_bytes()[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map_or(s.len(), |i| i + 3),
+        // OSC: terminated by BEL or ST
+        Some(']') => {
+            let bel = s[2..].find('\x07').map(|i| i + 3);
+            let st = s[2..].find("\x1b\\").map(|i| i + 4);
+            match (bel, st) {
+                (Some(x), Some(y)) => x.min(y),
+                (x, y) => x.or(y).unwrap_or(s.len()),
+            }
+        }
+        Some(c) => 1 + c.len_utf8(),
+        None => 1,
+    };
+    s.split_at(len)
+}
+
+/// Update `style` according to the parameters of an SGR sequence.
+fn apply_sgr(style: &mut Style, base: Style, params: &str) {
+    // An empty parameter means zero
+    let mut codes = params.split(';').map(|p| p.parse::<u32>().unwrap_or(0));
+
+    while let Some(code) = codes.next() {
+        match code {
+            0 => *style = base,
+            1 => style.modifier.insert(Modifier::BOLD),
+            2 => style.modifier.insert(Modifier::DIM),
+            3 => style.modifier.insert(Modifier::ITALIC),
+            4 => style.modifier.insert(Modifier::UNDERLINED),
+            5 => style.modifier.insert(Modifier::SLOW_BLINK),
+            7 => style.modifier.insert(Modifier::REVERSED),
+            9 => style.modifier.insert(Modifier::CROSSED_OUT),
+            22 => style.modifier.remove(Modifier::BOLD | Modifier::DIM),
+            23 => style.modifier.remove(Modifier::ITALIC),
+            24 => style.modifier.remove(Modifier::UNDERLINED),
+            25 => style.modifier.remove(Modifier::SLOW_BLINK),
+            27 => style.modifier.remove(Modifier::REVERSED),
+            29 => style.modifier.remove(Modifier::CROSSED_OUT),
+            30..=37 => style.fg = COLORS[code as usize - 30],
+            38 => {
+                if let Some(color) = extended_color(&mut codes) {
+                    style.fg = color;
+                }
+            }
+            39 => style.fg = base.fg,
+            40..=47 => style.bg = COLORS[code as usize - 40],
+            48 => {
+                if let Some(color) = extended_color(&mut codes) {
+                    style.bg = color;
+                }
+            }
+            49 => style.bg = base.bg,
+            90..=97 => style.fg = BRIGHT_COLORS[code as usize - 90],
+            100..=107 => style.bg = BRIGHT_COLORS[code as usize - 100],
+            _ => {}
+        }
+    }
+}
+
+/// Parse the arguments of SGR 38 and 48, i.e., `5;INDEX` or `2;R;G;B`.
+fn extended_color(codes: &mut impl Iterator<Item = u32>) -> Option<Color> {
+    let mut next = || codes.next().and_then(|x| u8::try_from(x).ok());
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styled_text_sgr() {
+        let base = Style::default().fg(Color::Yellow);
+        let text = to_styled_text(
+            "\x1b[1;32mok\x1b[0m 3/4 \x1b[38;5;208mwarn\x1b[39m\x1b[K",
+            base,
+        );
+        assert_eq!(
+            text,
+            [
+                Text::styled("ok", base.fg(Color::Green).modifier(Modifier::BOLD)),
+                Text::styled(" 3/4 ", base),
+                Text::styled("warn", base.fg(Color::Indexed(208))),
+            ]
+        );
+    }
+
+    #[test]
+    fn strip_escapes() {
+        assert_eq!(
+            strip("\x1b]0;title\x07\x1b[2K\r\x1b[1m5\x1b[0m/10\x1b["),
+            "\r5/10"
+        );
+    }
+}
@@ -0,0 +1,50 @@
+//! Copies text to the system clipboard using the platform's command-line
+//! utility.
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// The commands tried in order. Each reads the text from stdin.
+const COMMANDS: &[&[&str]] = &[
+    &["pbcopy"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+pub fn copy(text: &str) -> Result<(), io::Error> {
+    for cmd in COMMANDS {
+        let child = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Closing stdin signals the end of the text
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+
+        let status = child.wait()?;
+        return if status.success() {
+            Ok(())
+        } else {
+            // e.g., no display server to talk to
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("`{}` exited with {}", cmd[0], status),
+            ))
+        };
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard utility (pbcopy, wl-copy, xclip, or xsel) was found",
+    ))
+}
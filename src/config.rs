@@ -0,0 +1,349 @@
+//! Loads default options from a configuration file.
+//!
+//! The configuration file is a TOML document whose keys are the long names of
+//! command-line options, e.g.:
+//!
+//! ```toml
+//! unit = "ibytes"
+//! watch = true
+//! ```
+//!
+//! The options are converted to command-line arguments and inserted before
+//! the actual ones, so the latter take precedence. Flags have no negated
+//! forms, so a flag set to `true` in the file can't be turned off on the
+//! command line.
+//!
+//! Named profiles can be defined in `profiles` and selected by `--profile`.
+//! Their options replace the top-level ones (a flag set to `false` turns off
+//! the top-level one), and they may specify the command to execute if none is
+//! given:
+//!
+//! ```toml
+//! [profiles.rsync]
+//! regex = '(\d+)%'
+//! interval = "5s"
+//! command = ["rsync", "--info=progress2", "src/", "dst/"]
+//! ```
+//!
+//! A configuration file in the current directory may come from an untrusted
+//! source (e.g., a cloned repository), so it can only specify the options that
+//! control parsing and display (`UNTRUSTED_KEYS`). The options that run
+//! commands, such as `command` and `on-complete`, or access files, such as
+//! `tee` and `load-session`, are only honored in the one in
+//! `$XDG_CONFIG_HOME`.
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = "pvfilt.toml";
+
+/// The alternative path of the configuration file in `$XDG_CONFIG_HOME`.
+const DIR_FILE_NAME: &str = "pvfilt/config.toml";
+
+/// The keys allowed in a configuration file in the current directory. They
+/// neither run commands nor access files. New options are rejected until
+/// they are added here.
+const UNTRUSTED_KEYS: &[&str] = &[
+    "watch",
+    "interval",
+    "until-exit",
+    "success-codes",
+    "success-pattern",
+    "unit",
+    "no-autodetect",
+    "reject-outliers",
+    "max-mode",
+    "encoding",
+    "parse",
+    "invert",
+    "meter",
+    "cumulative",
+    "json",
+    "kv",
+    "json-max",
+    "percent",
+    "regex",
+    "match",
+    "ignore",
+    "annotate",
+    "max-output-bytes",
+    "no-ansi",
+    "interleave",
+    "keep-slowest",
+    "span",
+    "chart",
+    "log-scale",
+    "stack",
+    "window",
+    "aggregate",
+    "match-line",
+    "debug",
+    "rate-target",
+    "output-split",
+    "theme",
+    "no-gauge",
+    "gauge-color",
+    "eta-warn",
+    "eta-crit",
+    "gauge-background",
+    "median",
+    "smooth",
+    "eta-window",
+    "stall-timeout",
+    "sci",
+    "project",
+    "show-match",
+    "grid",
+    "precision",
+    "reset-mode",
+    "histogram-bins",
+    "initial-delay",
+    "min-sample-interval",
+    "warmup",
+    "max-duration",
+    "no-ui",
+    "dump",
+    "quit-key",
+    "no-esc-quit",
+    "no-ctrl-c-quit",
+    "max",
+];
+
+/// The options loaded from the configuration file.
+#[derive(Debug, Default)]
+pub struct Defaults {
+    /// The command-line arguments to insert before the actual ones.
+    pub args: Vec<OsString>,
+    /// The command to execute if none is given.
+    pub cmd: Vec<OsString>,
+}
+
+/// Locate the configuration file. The current directory is searched first,
+/// and then `$XDG_CONFIG_HOME` (defaults to `~/.config`), where
+/// `pvfilt/config.toml` is preferred to `pvfilt.toml`. The returned flag
+/// indicates whether the file is trusted, i.e., not in the current directory.
+fn find_config_file() -> Option<(PathBuf, bool)> {
+    let local = PathBuf::from(FILE_NAME);
+    if local.is_file() {
+        return Some((local, false));
+    }
+
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    [DIR_FILE_NAME, FILE_NAME]
+        .iter()
+        .map(|name| config_home.join(name))
+        .find(|path| path.is_file())
+        .map(|path| (path, true))
+}
+
+/// Load the configuration file (if any) and convert its contents, along with
+/// those of the given profile, to command-line arguments.
+pub fn load_defaults(profile: Option<&str>) -> Result<Defaults, io::Error> {
+    let (path, trusted) = if let Some(found) = find_config_file() {
+        found
+    } else if let Some(profile) = profile {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown profile `{}`: no configuration file", profile),
+        ));
+    } else {
+        return Ok(Defaults::default());
+    };
+
+    let invalid_data = |msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+
+    let text = fs::read_to_string(&path)?;
+    let mut table: toml::value::Table =
+        toml::from_str(&text).map_err(|e| invalid_data(e.to_string()))?;
+    let profiles = table.remove("profiles");
+
+    if !trusted {
+        check_untrusted(&table).map_err(invalid_data)?;
+    }
+
+    if let Some(name) = profile {
+        let profile = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .ok_or_else(|| invalid_data(format!("unknown profile `{}`", name)))?;
+        let profile = profile
+            .as_table()
+            .ok_or_else(|| invalid_data(format!("profile `{}` is not a table", name)))?;
+        if !trusted {
+            check_untrusted(profile).map_err(invalid_data)?;
+        }
+        merge_profile(&mut table, profile);
+    }
+
+    let mut defaults = Defaults::default();
+    table_to_defaults(&table, &mut defaults).map_err(invalid_data)?;
+
+    Ok(defaults)
+}
+
+/// Replace the entries of `table` with those of `profile`. `foo_bar` and
+/// `foo-bar` are treated as the same key.
+fn merge_profile(table: &mut toml::value::Table, profile: &toml::value::Table) {
+    let normalize = |key: &str| key.replace('_', "-");
+    for (key, value) in profile {
+        let existing: Vec<_> = (table.keys())
+            .filter(|existing| normalize(existing) == normalize(key))
+            .cloned()
+            .collect();
+        for existing in existing {
+            table.remove(&existing);
+        }
+        table.insert(key.clone(), value.clone());
+    }
+}
+
+/// Reject the options not in `UNTRUSTED_KEYS`.
+fn check_untrusted(table: &toml::value::Table) -> Result<(), String> {
+    match table
+        .keys()
+        .find(|key| !UNTRUSTED_KEYS.contains(&&*key.replace('_', "-")))
+    {
+        Some(key) => Err(format!(
+            "`{}` can't be set in the current directory; move it to $XDG_CONFIG_HOME/{}",
+            key, DIR_FILE_NAME
+        )),
+        None => Ok(()),
+    }
+}
+
+fn table_to_defaults(table: &toml::value::Table, out: &mut Defaults) -> Result<(), String> {
+    for (key, value) in table {
+        if key == "command" {
+            out.cmd = command_from_value(value)?;
+        } else {
+            table_entry_to_args(key, value, &mut out.args)?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert `command`, an array of arguments, to a command.
+fn command_from_value(value: &toml::Value) -> Result<Vec<OsString>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| "`command`: expected an array of strings".to_owned())?
+        .iter()
+        .map(|value| {
+            value
+                .as_str()
+                .map(OsString::from)
+                .ok_or_else(|| "`command`: expected an array of strings".to_owned())
+        })
+        .collect()
+}
+
+fn table_entry_to_args(
+    key: &str,
+    value: &toml::Value,
+    out: &mut Vec<OsString>,
+) -> Result<(), String> {
+    let flag = format!("--{}", key.replace('_', "-"));
+
+    match value {
+        toml::Value::Boolean(true) => out.push(flag.into()),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(s) => {
+            out.push(flag.into());
+            out.push(s.into());
+        }
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Datetime(_) => {
+            out.push(flag.into());
+            out.push(value.to_string().into());
+        }
+        toml::Value::Array(values) => {
+            for value in values {
+                table_entry_to_args(key, value, out)?;
+            }
+        }
+        toml::Value::Table(_) => {
+            return Err(format!("`{}`: a table is not a valid option value", key));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_overrides_top_level() {
+        let mut table: toml::value::Table = toml::from_str(
+            r#"
+            unit = "bytes"
+            interval = "1s"
+            no_ui = true
+            watch = true
+            [profiles.slow]
+            interval = "5s"
+            no-ui = false
+            command = ["make", "-j4"]
+            "#,
+        )
+        .unwrap();
+        let profiles = table.remove("profiles").unwrap();
+        merge_profile(&mut table, profiles["slow"].as_table().unwrap());
+
+        let mut defaults = Defaults::default();
+        table_to_defaults(&table, &mut defaults).unwrap();
+
+        assert_eq!(
+            defaults.args,
+            ["--interval", "5s", "--unit", "bytes", "--watch"]
+        );
+        assert_eq!(defaults.cmd, ["make", "-j4"]);
+    }
+
+    #[test]
+    fn untrusted_rejects_commands_and_paths() {
+        let table: toml::value::Table = toml::from_str(
+            r#"
+            unit = "bytes"
+            watch = true
+            max_output_bytes = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(check_untrusted(&table), Ok(()));
+
+        for text in &[
+            r#"command = ["rm", "-rf", "/"]"#,
+            r#"on-complete = "sh evil.sh""#,
+            r#"on_threshold = ["1:sh evil.sh"]"#,
+            r#"env = ["PATH=."]"#,
+            r#"cmd = ["sh evil.sh"]"#,
+            r#"shell = true"#,
+            r#"cwd = "/""#,
+            r#"tee = "/home/user/.bashrc""#,
+            r#"export-csv = "/home/user/.bashrc""#,
+            r#"save_session = "/home/user/.bashrc""#,
+            r#"print-eta-json = "/home/user/.bashrc""#,
+            r#"render-svg = "/home/user/.bashrc""#,
+            r#"watch-file = "/home/user/.ssh/id_rsa""#,
+            r#"load-session = "/home/user/.ssh/id_rsa""#,
+            r#"notify = true"#,
+            r#"profile = "other""#,
+            r#"no-such-option = true"#,
+        ] {
+            let table: toml::value::Table = toml::from_str(text).unwrap();
+            assert!(check_untrusted(&table).is_err(), "{}", text);
+        }
+    }
+}
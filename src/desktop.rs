@@ -0,0 +1,50 @@
+//! Shows desktop notifications using the platform's command-line utility.
+use std::{
+    io,
+    process::{Command, Stdio},
+};
+
+pub fn notify(summary: &str, body: &str) -> Result<(), io::Error> {
+    let commands: &[&[&str]] = &[
+        &["notify-send", "--app-name=pvfilt", summary, body],
+        &[
+            "osascript",
+            "-e",
+            &format!(
+                "display notification {} with title {}",
+                applescript_string(body),
+                applescript_string(summary)
+            ),
+        ],
+    ];
+
+    for cmd in commands {
+        let status = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        return match status {
+            Ok(status) if status.success() => Ok(()),
+            // e.g., no notification daemon to talk to
+            Ok(status) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("`{}` exited with {}", cmd[0], status),
+            )),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => Err(e),
+        };
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no notification utility (notify-send or osascript) was found",
+    ))
+}
+
+/// Quote `s` as an AppleScript string literal.
+fn applescript_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -1,211 +1,1064 @@
 use humantime::format_duration;
 use std::{
+    borrow::Cow,
+    collections::VecDeque,
     io,
+    str::FromStr,
     time::{Duration, Instant},
 };
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     terminal::Frame,
-    widgets::{Axis, Block, Borders, Chart, Dataset, Gauge, Marker, Paragraph, Text, Widget},
+    widgets::{
+        Axis, BarChart, Block, Borders, Chart, Dataset, Gauge, Marker, Paragraph, Text, Widget,
+    },
     Terminal,
 };
 
-use super::AppState;
+use super::{
+    analysis::{self, Analyzer, ResetMode, Sample},
+    ansi, keys, runner,
+    theme::Theme,
+    unit::Unit,
+    AppState, Pane, WorkerState,
+};
+
+/// The minimum terminal size required to draw the full layout. The compact
+/// layout is used on smaller terminals.
+const MIN_SIZE: (u16, u16) = (60, 15);
+
+/// The minimum terminal width required to draw the compact layout.
+const COMPACT_MIN_WIDTH: u16 = 20;
+
+/// The minimum time span (in seconds) shown in the chart.
+pub(crate) const MIN_TIME_SCALE: f64 = 1.0;
+
+/// The fraction of the visible time span moved by a pan.
+pub(crate) const PAN_STEP: f64 = 0.25;
+
+/// Shown at the top of an output pane if the runner discarded some of it.
+const TRUNCATED_MARKER: &str = "…(truncated)";
+
+/// Parse a color name (e.g., `lightred`) or a 256-color palette index.
+pub(crate) fn parse_color(s: &str) -> Result<Color, String> {
+    Ok(match s {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => Color::Indexed(s.parse().map_err(|_| format!("unknown color: {}", s))?),
+    })
+}
+
+/// The relative widths of the stdout and stderr panes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct OutputSplit {
+    pub(crate) stdout: u32,
+    pub(crate) stderr: u32,
+}
+
+impl OutputSplit {
+    /// The largest part accepted by `OutputSplit::from_str`, which keeps the
+    /// layout ratios from overflowing.
+    const MAX_PART: u32 = 100;
+
+    /// The splits cycled through by `OutputSplit::next`.
+    const PRESETS: &'static [OutputSplit] = &[
+        OutputSplit {
+            stdout: 1,
+            stderr: 1,
+        },
+        OutputSplit {
+            stdout: 3,
+            stderr: 1,
+        },
+        OutputSplit {
+            stdout: 1,
+            stderr: 3,
+        },
+    ];
+
+    /// Get the next preset split. Returns the first one if `self` is not a
+    /// preset.
+    pub(crate) fn next(self) -> Self {
+        let i = Self::PRESETS.iter().position(|&x| x == self);
+        Self::PRESETS[i.map_or(0, |i| (i + 1) % Self::PRESETS.len())]
+    }
+}
+
+impl FromStr for OutputSplit {
+    type Err = String;
+
+    /// Parse a split in the `STDOUT:STDERR` format, e.g., `1:3`. Each part
+    /// must be between 0 and `MAX_PART`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid split (expected `STDOUT:STDERR`, each up to {}): {}",
+                Self::MAX_PART,
+                s
+            )
+        };
+        let mut parts = s.splitn(2, ':');
+        let mut next = || {
+            parts
+                .next()
+                .and_then(|part| part.trim().parse::<u32>().ok())
+                .filter(|&part| part <= Self::MAX_PART)
+                .ok_or_else(invalid)
+        };
+        let (stdout, stderr) = (next()?, next()?);
+        if stdout == 0 && stderr == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { stdout, stderr })
+    }
+}
+
+/// The quantities plotted in the time series chart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ChartMode {
+    Value,
+    Rate,
+    /// The value chart above the rate chart.
+    Both,
+}
+
+impl ChartMode {
+    pub(crate) const NAMES: &'static [&'static str] = &["value", "rate", "both"];
+
+    /// Get the mode following `self` in `NAMES`.
+    pub(crate) fn next(self) -> Self {
+        match self {
+            ChartMode::Value => ChartMode::Rate,
+            ChartMode::Rate => ChartMode::Both,
+            ChartMode::Both => ChartMode::Value,
+        }
+    }
+}
+
+impl FromStr for ChartMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "value" => Ok(ChartMode::Value),
+            "rate" => Ok(ChartMode::Rate),
+            "both" => Ok(ChartMode::Both),
+            _ => Err(format!("unknown chart mode: {}", s)),
+        }
+    }
+}
 
 impl AppState {
+    /// Get how long the value has stayed the same if it's at least
+    /// `--stall-timeout`. Doesn't advance while paused.
+    fn stalled_for(&self, analyzer: &Analyzer) -> Option<Duration> {
+        let now = self.worker.pause.paused_at().unwrap_or_else(Instant::now);
+        analyzer
+            .stalled_for(now)
+            .map(|stalled| Duration::from_secs(stalled.as_secs()))
+    }
+
+    /// Get the instants at which the chart's time axis starts and ends
+    /// without zooming. With `--until-exit`, the axis spans from the launch to
+    /// the exit (or now).
+    fn time_range(&self, samples: &VecDeque<Sample>) -> Option<(Instant, Instant)> {
+        if self.opt.until_exit {
+            if let Some(Ok(output)) = &*self.worker.last_output.lock().unwrap() {
+                // Freeze the chart while paused
+                let end = output.started + output.elapsed();
+                let end = self.worker.pause.paused_at().map_or(end, |at| end.min(at));
+                return Some((output.started, end));
+            }
+        }
+        Some((samples.front()?.instant, samples.back()?.instant))
+    }
+
+    /// Get the time span (in seconds) currently shown in the chart.
+    pub(crate) fn visible_time_span(&self) -> f64 {
+        let samples = &self.worker.analyzer.lock().unwrap().samples;
+        (self.full_time_span(samples) / self.zoom).max(MIN_TIME_SCALE)
+    }
+
+    /// Get the time span (in seconds) shown in the chart without zooming.
+    pub(crate) fn full_time_span(&self, samples: &VecDeque<Sample>) -> f64 {
+        if let Some(span) = self.opt.span.or(self.opt.window) {
+            span.as_secs_f64()
+        } else if let Some((start, end)) = self.time_range(samples) {
+            end.duration_since(start).as_secs_f64()
+        } else {
+            0.0
+        }
+    }
+
+    /// Check if the command has exited for good, i.e., with `--until-exit`.
+    pub(crate) fn has_exited(&self) -> bool {
+        self.opt.until_exit
+            && match &*self.worker.last_output.lock().unwrap() {
+                Some(Ok(output)) => output.status.is_some(),
+                Some(Err(_)) => true,
+                None => false,
+            }
+    }
+
+    /// Describe the state of the run for `--until-exit`.
+    fn run_state(&self) -> Option<(String, Color)> {
+        if !self.opt.until_exit {
+            return None;
+        }
+        Some(match &*self.worker.last_output.lock().unwrap() {
+            None => ("WAITING".to_owned(), self.theme.warning),
+            Some(Err(e)) => (format!("FAILED {}", e), self.theme.error),
+            Some(Ok(output)) => {
+                let elapsed = format_duration(Duration::from_secs(output.elapsed().as_secs()));
+                match (output.status, output.success) {
+                    (Some(status), Some(true)) => {
+                        (format!("DONE {} ({})", elapsed, status), self.theme.line)
+                    }
+                    (Some(status), _) => {
+                        (format!("FAILED {} ({})", elapsed, status), self.theme.error)
+                    }
+                    (None, _) => (format!("RUNNING {}", elapsed), self.theme.warning),
+                }
+            }
+        })
+    }
+
+    /// Draw the chart of a `--cmd` other than the main command, which follows
+    /// the main chart's mode and zoom.
+    fn draw_extra_chart(
+        &self,
+        f: &mut Frame<impl Backend>,
+        area: Rect,
+        cmd: &str,
+        worker: &WorkerState,
+    ) {
+        let theme = &self.theme;
+        let unit = &self.opt.unit;
+        let (sci, precision) = (self.opt.sci, self.opt.precision);
+        let title_style = Style::default().fg(theme.border);
+
+        let mut b_chart = Block::default()
+            .border_style(Style::default().fg(theme.border))
+            .borders(Borders::BOTTOM);
+        b_chart.render(f, area);
+        let area = b_chart.inner(area);
+
+        let analyzer = worker.analyzer.lock().unwrap();
+        let samples = analyzer.filtered_samples();
+
+        let mut title = format!("$ {}", cmd.replace(|c: char| c.is_control(), " "));
+        let start = match (samples.front(), analyzer.progress()) {
+            (Some(first), Some(progress)) => {
+                title += &format!(" — {}", unit.format_value(progress.value, precision));
+                if let Some(rate) = progress.recent_rate {
+                    title += &format!(
+                        ", {}",
+                        RateFormat::new(unit, rate, sci, precision).format(rate)
+                    );
+                }
+                first.instant
+            }
+            _ => {
+                Paragraph::new(
+                    [
+                        Text::styled(title, title_style),
+                        Text::styled("\nWaiting for more data...", title_style),
+                    ]
+                    .iter(),
+                )
+                .render(f, area);
+                return;
+            }
+        };
+
+        // The time axis ends now like the main chart's, but without the pan
+        let end = worker.pause.paused_at().unwrap_or_else(Instant::now);
+        let full_span = end.saturating_duration_since(start).as_secs_f64();
+        let time_scale = (full_span / self.zoom).max(MIN_TIME_SCALE);
+        let data: Vec<_> = samples
+            .iter()
+            .map(|s| {
+                (
+                    -end.saturating_duration_since(s.instant).as_secs_f64(),
+                    s.value,
+                )
+            })
+            .collect();
+
+        let in_view = |data: Vec<(f64, f64)>| -> Vec<_> {
+            data.into_iter()
+                .filter(|&(t, _)| t >= -time_scale)
+                .collect()
+        };
+        let format_value = |v: f64, _: f64| unit.format_value(v, precision);
+        let format_rate =
+            |v: f64, magnitude: f64| RateFormat::new(unit, magnitude, sci, precision).format(v);
+        let (y_title, data, format_y): (_, _, &dyn Fn(f64, f64) -> String) = match self.chart_mode {
+            ChartMode::Value => ("Value", in_view(data), &format_value),
+            ChartMode::Rate | ChartMode::Both => {
+                let data_rate = analysis::analyze_rate(data.into_iter()).collect();
+                let data_rate = match self.opt.smooth {
+                    Some(smoothing) => smoothing.apply(data_rate),
+                    None => data_rate,
+                };
+                ("Rate", in_view(data_rate), &format_rate)
+            }
+        };
+
+        TimeSeriesChart {
+            title: &title,
+            time_scale,
+            pan: 0.0,
+            exited: false,
+            y_title,
+            series: &[(y_title, theme.line, &data)],
+            annotations: &[],
+            log_scale: self.log_scale,
+            grid: self.opt.grid,
+            format_y,
+        }
+        .draw(f, area, theme, title_style);
+    }
+
+    /// Draw the final results in place of the charts.
+    fn draw_summary(&self, f: &mut Frame<impl Backend>, area: Rect, analyzer: &Analyzer) {
+        let theme = &self.theme;
+        let unit = &self.opt.unit;
+        let samples = analyzer.filtered_samples();
+        let progress = analyzer.progress();
+
+        let (headline, color) = match &*self.worker.last_output.lock().unwrap() {
+            Some(Ok(runner::CmdOutput {
+                status: Some(status),
+                success,
+                ..
+            })) if self.opt.until_exit => {
+                if *success == Some(true) {
+                    (format!("Done ({})", status), theme.line)
+                } else {
+                    (format!("Failed ({})", status), theme.error)
+                }
+            }
+            _ => ("Done (reached the maximum value)".to_owned(), theme.line),
+        };
+
+        let elapsed = match &*self.worker.last_output.lock().unwrap() {
+            Some(Ok(output)) if self.opt.until_exit => output.elapsed(),
+            _ => match (samples.front(), samples.back()) {
+                (Some(first), Some(last)) => last.instant.duration_since(first.instant),
+                _ => Duration::from_secs(0),
+            },
+        };
+
+        let peak_rate = analyzer.peak_rate();
+
+        let label_style = Style::default().fg(theme.border);
+        let unknown = || Text::styled("(unknown)", label_style);
+
+        let mut fragments = vec![
+            Text::styled(
+                headline,
+                Style::default().fg(color).modifier(Modifier::BOLD),
+            ),
+            Text::raw("\n\n"),
+            Text::styled("Total     ", label_style),
+        ];
+        if let Some(progress) = &progress {
+            fragments.push(Text::raw(
+                unit.format_value(progress.value, self.opt.precision),
+            ));
+            if let Some(max) = progress.max {
+                fragments.push(Text::styled("/", label_style));
+                fragments.push(Text::raw(unit.format_value(max, self.opt.precision)));
+            }
+        } else {
+            fragments.push(unknown());
+        }
+        fragments.extend(vec![
+            Text::raw("\n"),
+            Text::styled("Elapsed   ", label_style),
+            Text::raw(format_duration(Duration::from_secs(elapsed.as_secs())).to_string()),
+            Text::raw("\n"),
+            Text::styled("Avg rate  ", label_style),
+        ]);
+        let rate_format = RateFormat::new(
+            unit,
+            peak_rate.unwrap_or(0.0),
+            self.opt.sci,
+            self.opt.precision,
+        );
+        let rate_text = |rate: Option<f64>| match rate.filter(|rate| rate.is_finite()) {
+            Some(rate) => vec![
+                Text::raw(rate_format.number(rate)),
+                Text::styled(rate_format.suffix(), label_style),
+            ],
+            None => vec![unknown()],
+        };
+        fragments.extend(rate_text(progress.map(|p| p.rate)));
+        fragments.push(Text::raw("\n"));
+        fragments.push(Text::styled("Peak rate ", label_style));
+        fragments.extend(rate_text(peak_rate));
+
+        Paragraph::new(fragments.iter())
+            .block(
+                Block::default()
+                    .title("Summary")
+                    .title_style(label_style)
+                    .border_style(label_style)
+                    .borders(Borders::BOTTOM),
+            )
+            .wrap(true)
+            .render(f, area);
+    }
+
+    /// Describe the progress in a single line, e.g., `42/100 42% 1.2/s ETA 3m`.
+    /// Returns `None` if there's no progress to describe yet.
+    pub(crate) fn status_line(&self) -> Option<String> {
+        let unit = &self.opt.unit;
+        let analyzer = self.worker.analyzer.lock().unwrap();
+        let progress = analyzer.progress()?;
+
+        let mut line = unit.format_value(progress.value, self.opt.precision);
+        if let (Some(max), Some(ratio)) = (progress.max, progress.ratio()) {
+            line += &format!(
+                "/{} {:.0}%",
+                unit.format_value(max, self.opt.precision),
+                ratio * 100.0
+            );
+        }
+        if progress.warming_up {
+            line += " measuring…";
+        } else {
+            let rate_format =
+                RateFormat::new(unit, progress.rate, self.opt.sci, self.opt.precision);
+            line += &format!(" {}", rate_format.format(progress.rate));
+        }
+        if let Some(eta) = progress.eta {
+            let eta = format_duration(Duration::from_secs(eta as u64));
+            line += &format!(" ETA {}", eta);
+        }
+        if let Some(stalled) = self.stalled_for(&analyzer) {
+            line += &format!(" STALLED for {}", format_duration(stalled));
+        }
+        Some(line)
+    }
+
+    /// Draw the progress in a single line.
+    fn draw_compact(&self, f: &mut Frame<impl Backend>) {
+        let size = f.size();
+        let line = self
+            .status_line()
+            .unwrap_or_else(|| "Waiting for more data...".to_owned());
+
+        Paragraph::new(
+            [Text::raw(truncate_with_ellipsis(
+                &line,
+                size.width as usize,
+            ))]
+            .iter(),
+        )
+        .render(f, size);
+    }
+
     pub(crate) fn draw(&mut self, terminal: &mut Terminal<impl Backend>) -> Result<(), io::Error> {
         terminal.draw(|mut f| {
             let size = f.size();
-            let title_style = Style::default().fg(Color::DarkGray);
-            let border_style = Style::default().fg(Color::DarkGray);
+            if size.width < MIN_SIZE.0 || size.height < MIN_SIZE.1 {
+                if size.width >= COMPACT_MIN_WIDTH {
+                    self.draw_compact(&mut f);
+                } else {
+                    draw_too_small(&mut f, &self.theme);
+                }
+                return;
+            }
+
+            let theme = self.theme;
+            let title_style = Style::default().fg(theme.border);
+            let border_style = Style::default().fg(theme.border);
+            let unit = &self.opt.unit;
+
+            let header_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(0)
+                .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+                .split(size);
 
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(0)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-                .split(size);
+                .split(header_chunks[1]);
 
             // ---------------------------------------------------------------
-            //  Charts
+            //  Header
 
-            let mut b_chart = Block::default()
-                .border_style(border_style)
-                .borders(Borders::BOTTOM);
-            b_chart.render(&mut f, chunks[0]);
+            let mut header = Vec::new();
+            if let Some((state, color)) = self.run_state() {
+                header.push(Text::styled(
+                    format!("{} ", state),
+                    Style::default().fg(color),
+                ));
+            }
+            let header_len: usize = header
+                .iter()
+                .map(|text| match text {
+                    Text::Raw(s) | Text::Styled(s, _) => s.chars().count(),
+                })
+                .sum();
 
-            let chart_chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .margin(0)
-                .constraints(
-                    [
-                        Constraint::Min(0),
-                        Constraint::Length(1),
-                        Constraint::Length(30),
-                    ]
-                    .as_ref(),
-                )
-                .split(b_chart.inner(chunks[0]));
+            let cmd_line = self.cmd_string.replace(|c: char| c.is_control(), " ");
+            let prompt = if self.opt.watch_file.is_some() || self.opt.reads_stdin() {
+                "< "
+            } else {
+                "$ "
+            };
+            header.push(Text::styled(prompt, title_style));
+            header.push(Text::raw(truncate_with_ellipsis(
+                &cmd_line,
+                (header_chunks[0].width as usize).saturating_sub(header_len + 2),
+            )));
+            Paragraph::new(header.iter()).render(&mut f, header_chunks[0]);
+
+            // ---------------------------------------------------------------
+            //  Charts
 
-            let b_time_series = Block::default()
-                .title("Time Series")
-                .title_style(title_style);
+            // The charts of the other `--cmd`s are stacked below the main one
+            let num_charts = self.extra_workers.len() as u32 + 1;
+            let chart_areas = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(0)
+                .constraints(vec![Constraint::Ratio(1, num_charts); num_charts as usize])
+                .split(chunks[0]);
+            for ((cmd, worker), &area) in self.extra_workers.iter().zip(&chart_areas[1..]) {
+                self.draw_extra_chart(&mut f, area, cmd, worker);
+            }
 
             let analyzer = self.worker.analyzer.lock().unwrap();
-            let samples = &analyzer.samples;
 
-            let (time_scale, time_origin) =
-                if let (Some(first), Some(last)) = (samples.front(), samples.back()) {
-                    let scale = last
-                        .instant
-                        .duration_since(first.instant)
-                        .as_secs_f64()
-                        .max(1.0);
+            if self.done {
+                self.draw_summary(&mut f, chart_areas[0], &analyzer);
+            } else {
+                let mut b_chart = Block::default()
+                    .border_style(border_style)
+                    .borders(Borders::BOTTOM);
+                b_chart.render(&mut f, chart_areas[0]);
+
+                let chart_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .margin(0)
+                    .constraints(
+                        [
+                            Constraint::Min(0),
+                            Constraint::Length(1),
+                            Constraint::Length(30),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(b_chart.inner(chart_areas[0]));
 
-                    (scale, last.instant - Duration::from_secs_f64(scale))
+                let samples = analyzer.filtered_samples();
+                let samples = &*samples;
+
+                let (time_scale, time_origin) = if let Some((start, end)) = self.time_range(samples)
+                {
+                    let full_span = self.full_time_span(samples);
+                    let scale = (full_span / self.zoom).max(MIN_TIME_SCALE);
+                    // Keep the viewport within the history
+                    self.pan = self.pan.min((full_span - scale).max(0.0));
+                    let origin = end
+                        .checked_sub(Duration::from_secs_f64(scale + self.pan))
+                        .unwrap_or(start);
+
+                    (scale, origin)
                 } else {
-                    (1.0, Instant::now())
+                    (MIN_TIME_SCALE, Instant::now())
                 };
+                let pan = self.pan;
 
-            let data: Vec<_> = samples
-                .iter()
-                .rev()
-                .scan((), |_, s| {
-                    (if let Some(t) = s.instant.checked_duration_since(time_origin) {
-                        Some((t.as_secs_f64() - time_scale, s.value))
-                    } else {
-                        None
+                let time_series_title = match (self.zoom > 1.0, pan > 0.0) {
+                    (true, true) => format!(
+                        "Time Series (zoom ×{:.1}, {} back)",
+                        self.zoom,
+                        format_duration(Duration::from_secs(pan.round() as u64))
+                    ),
+                    (true, false) => format!("Time Series (zoom ×{:.1})", self.zoom),
+                    _ => "Time Series".to_owned(),
+                };
+
+                let data: Vec<_> = samples
+                    .iter()
+                    .rev()
+                    .scan((), |_, s| {
+                        s.instant
+                            .checked_duration_since(time_origin)
+                            .map(|t| (t.as_secs_f64() - time_scale, s.value))
                     })
-                })
-                .collect();
+                    // Exclude the samples to the right of the viewport
+                    .filter(|&(t, _)| t <= 0.0)
+                    .collect();
 
-            let data_rate: Vec<_> = analyze_rate(data.iter().map(|&(t, v)| (-t, v)))
-                .map(|(t, v)| (-t, -v))
-                .collect();
+                // (time, text)
+                let annotations: Vec<_> = analyzer
+                    .annotations
+                    .iter()
+                    .filter_map(|annotation| {
+                        let t = annotation.instant.checked_duration_since(time_origin)?;
+                        Some((t.as_secs_f64() - time_scale, annotation.text.as_str()))
+                    })
+                    .collect();
 
-            let value_range = if data_rate.is_empty() {
-                [0.0, 1.0]
-            } else {
-                use std::f64::NAN;
-                let range = [
-                    data_rate.iter().map(|s| s.1).fold(NAN, f64::min),
-                    data_rate.iter().map(|s| s.1).fold(NAN, f64::max),
-                ];
-                let width = range[1] - range[0];
-                let mut value_range = [range[0] - width * 0.1, range[1] + width * 0.1];
-
-                if range[0] >= 0.0 {
-                    value_range[0] = value_range[0].max(0.0);
+                let smooth = |data_rate: Vec<_>| match self.opt.smooth {
+                    Some(smoothing) => smoothing.apply(data_rate),
+                    None => data_rate,
+                };
+
+                // A decrease marks the start of a new segment
+                let reset_mode = self.opt.reset_mode;
+                let in_segment =
+                    |&(_, v): &(f64, f64)| reset_mode != ResetMode::Segment || v >= 0.0;
+
+                let data_rate: Vec<_> = analysis::analyze_rate(data.iter().rev().cloned())
+                    .filter(in_segment)
+                    .collect();
+                let data_rate = smooth(data_rate);
+
+                // The extra series captured by named groups
+                let series_names = analyzer.series_names();
+                let data_extra_values: Vec<Vec<_>> = (0..series_names.len())
+                    .map(|i| {
+                        samples
+                            .iter()
+                            .filter_map(|s| {
+                                let t = s.instant.checked_duration_since(time_origin)?;
+                                let t = t.as_secs_f64() - time_scale;
+                                Some((t, s.series.get(i).copied()??)).filter(|_| t <= 0.0)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                // With `--stack`, each series in the value chart is the
+                // running total up to it
+                let data_stacked = if self.opt.stack && !series_names.is_empty() {
+                    let points: Vec<_> = samples
+                        .iter()
+                        .filter_map(|s| {
+                            let t = s.instant.checked_duration_since(time_origin)?;
+                            let t = t.as_secs_f64() - time_scale;
+                            let components =
+                                Some(Some(s.value)).into_iter().chain(s.series.clone());
+                            Some((t, components.collect())).filter(|_| t <= 0.0)
+                        })
+                        .collect();
+                    stack_series(&points, series_names.len() + 1)
+                } else {
+                    Vec::new()
+                };
+                let data_extra_rates: Vec<Vec<_>> = data_extra_values
+                    .iter()
+                    .map(|data| {
+                        smooth(
+                            analysis::analyze_rate(data.iter().cloned())
+                                .filter(in_segment)
+                                .collect(),
+                        )
+                    })
+                    .collect();
+
+                // The rate during the warmup period is dimmed
+                let steady_since = self.opt.warmup.map(|_| {
+                    analyzer.steady_since().map_or(f64::INFINITY, |instant| {
+                        instant
+                            .checked_duration_since(time_origin)
+                            .map_or(f64::NEG_INFINITY, |t| t.as_secs_f64() - time_scale)
+                    })
+                });
+                let (data_rate, data_rate_warmup): (Vec<(f64, f64)>, Vec<_>) = data_rate
+                    .into_iter()
+                    .partition(|&(t, _)| steady_since.map_or(true, |since| t > since));
+
+                let rate_target = self.opt.rate_target;
+
+                // The rate below the target is highlighted
+                let (data_rate, data_rate_below): (Vec<(f64, f64)>, Vec<_>) = data_rate
+                    .into_iter()
+                    .partition(|&(_, v)| rate_target.map_or(true, |target| v >= target));
+
+                // A flat line. `Chart` only plots points, so they are placed
+                // as densely as the braille resolution.
+                let flat_line = |v: f64| -> Vec<_> {
+                    let num_points = chart_chunks[0].width as usize * 2;
+                    (0..=num_points)
+                        .map(|i| (-time_scale * i as f64 / num_points as f64, v))
+                        .collect()
+                };
+                let data_target: Vec<_> = rate_target.map_or_else(Vec::new, flat_line);
+                let data_max: Vec<_> = samples
+                    .back()
+                    .and_then(|s| s.max)
+                    .map_or_else(Vec::new, flat_line);
+
+                let rate_range = value_range(
+                    data_rate
+                        .iter()
+                        .chain(&data_rate_below)
+                        .chain(&data_rate_warmup)
+                        .chain(data_extra_rates.iter().flatten())
+                        .map(|s| s.1)
+                        .chain(rate_target),
+                );
+
+                let rate_format = RateFormat::new(
+                    unit,
+                    rate_range[0].abs().max(rate_range[1].abs()),
+                    self.opt.sci,
+                    self.opt.precision,
+                );
+
+                // (label, color, data)
+                let mut value_series = vec![(
+                    analyzer.value_name().unwrap_or("Value"),
+                    theme.line,
+                    &data[..],
+                )];
+                let mut rate_series = vec![(
+                    analyzer.value_name().unwrap_or("Rate"),
+                    theme.line,
+                    &data_rate[..],
+                )];
+                for (i, &name) in series_names.iter().enumerate() {
+                    let color = theme.series[i % theme.series.len()];
+                    value_series.push((name, color, &data_extra_values[i][..]));
+                    rate_series.push((name, color, &data_extra_rates[i][..]));
+                }
+                for (s, data) in value_series.iter_mut().zip(&data_stacked) {
+                    s.2 = &data[..];
+                }
+                if !data_max.is_empty() {
+                    value_series.push(("Max", theme.border, &data_max[..]));
+                }
+                if !data_rate_warmup.is_empty() {
+                    rate_series.push(("Warmup", theme.border, &data_rate_warmup[..]));
+                }
+                if rate_target.is_some() {
+                    rate_series.push(("Below target", theme.error, &data_rate_below[..]));
+                    rate_series.push(("Target", theme.warning, &data_target[..]));
                 }
 
-                value_range
-            };
+                if self.show_histogram {
+                    let data_rate: Vec<_> = data_rate
+                        .iter()
+                        .chain(&data_rate_below)
+                        .chain(&data_rate_warmup)
+                        .cloned()
+                        .collect();
+                    draw_rate_histogram(
+                        &mut f,
+                        chart_chunks[0],
+                        &data_rate,
+                        self.opt.histogram_bins,
+                        &rate_format,
+                        &theme,
+                    );
+                } else {
+                    let charts = match self.chart_mode {
+                        ChartMode::Both => {
+                            let areas = Layout::default()
+                                .direction(Direction::Vertical)
+                                .constraints(
+                                    [Constraint::Percentage(50), Constraint::Percentage(50)]
+                                        .as_ref(),
+                                )
+                                .split(chart_chunks[0]);
+                            vec![(areas[0], ChartMode::Value), (areas[1], ChartMode::Rate)]
+                        }
+                        mode => vec![(chart_chunks[0], mode)],
+                    };
 
-            let dataset = Dataset::default()
-                .marker(Marker::Braille)
-                .style(Style::default().fg(Color::Green))
-                .data(&data_rate);
-
-            let time_scale_rounded = Duration::from_secs(time_scale as u64);
-
-            Chart::default()
-                .block(b_time_series)
-                .x_axis(
-                    Axis::default()
-                        .title("Time")
-                        .bounds([-time_scale - 0.1, 0.1])
-                        .labels(&[
-                            format!("{} ago", format_duration(time_scale_rounded)).as_str(),
-                            "now",
-                        ]),
-                )
-                .y_axis(
-                    Axis::default()
-                        .title("Value/Second")
-                        .bounds(value_range)
-                        .labels(&[
-                            format!("{:.04e}", value_range[0]),
-                            format!("{:.04e}", value_range[1]),
-                        ]),
-                )
-                .datasets(&[dataset])
-                .render(&mut f, chart_chunks[0]);
+                    let (sci, precision) = (self.opt.sci, self.opt.precision);
+                    let format_value = |v: f64, _: f64| unit.format_value(v, precision);
+                    let format_rate = |v: f64, magnitude: f64| {
+                        RateFormat::new(unit, magnitude, sci, precision).format(v)
+                    };
 
-            let mut b_status = Block::default().title("Status").title_style(title_style);
-            b_status.render(&mut f, chart_chunks[2]);
+                    for (i, (area, mode)) in charts.into_iter().enumerate() {
+                        let (y_title, series, format_y): (_, _, &dyn Fn(f64, f64) -> String) =
+                            match mode {
+                                ChartMode::Value => ("Value", &value_series, &format_value),
+                                _ => ("Rate", &rate_series, &format_rate),
+                            };
+                        TimeSeriesChart {
+                            // Only the topmost chart is titled
+                            title: if i == 0 { &time_series_title } else { "" },
+                            time_scale,
+                            pan,
+                            exited: self.has_exited(),
+                            y_title,
+                            series,
+                            annotations: &annotations,
+                            log_scale: self.log_scale,
+                            grid: self.opt.grid,
+                            format_y,
+                        }
+                        .draw(&mut f, area, &theme, title_style);
+                    }
+                }
 
-            let status_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(0)
-                .constraints([Constraint::Min(3), Constraint::Length(1)].as_ref())
-                .split(b_status.inner(chart_chunks[2]));
-
-            if samples.len() >= 2 {
-                let (front, back) = (data.first().unwrap(), data.last().unwrap());
-                let max = samples.back().unwrap().max;
-                let speed = (back.1 - front.1) / (back.0 - front.0);
-                let eta = (max - front.1) / speed;
-                let eta = if eta >= 0.0 {
-                    Some(format_duration(Duration::from_secs(eta as u64)))
+                let (status_title, status_title_style) = if self.worker.pause.paused_at().is_some()
+                {
+                    ("Status — PAUSED", title_style.fg(theme.warning))
                 } else {
-                    None
+                    ("Status", title_style)
                 };
+                let mut b_status = Block::default()
+                    .title(status_title)
+                    .title_style(status_title_style);
+                b_status.render(&mut f, chart_chunks[2]);
 
-                Paragraph::new(
-                    [
-                        Text::styled(format!("{}", front.1), Style::default()),
-                        Text::styled("/", Style::default().fg(Color::DarkGray)),
-                        Text::styled(format!("{}\n\n", max), Style::default()),
-                        Text::styled("Rate ", Style::default().fg(Color::DarkGray)),
+                let stalled = self.stalled_for(&analyzer);
+
+                let status_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(0)
+                    .constraints(
+                        [
+                            Constraint::Length(stalled.is_some() as u16 * 2),
+                            Constraint::Min(3),
+                            Constraint::Length(self.opt.show_match as u16),
+                            Constraint::Length(1),
+                            Constraint::Length(1),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(b_status.inner(chart_chunks[2]));
+
+                if let Some(stalled) = stalled {
+                    Paragraph::new(
+                        [Text::styled(
+                            format!(" STALLED for {} ", format_duration(stalled)),
+                            Style::default()
+                                .fg(theme.error)
+                                .modifier(Modifier::BOLD | Modifier::REVERSED),
+                        )]
+                        .iter(),
+                    )
+                    .render(&mut f, status_chunks[0]);
+                }
+
+                if let (true, Some(line)) = (
+                    self.opt.show_match,
+                    analyzer.samples.back().and_then(|s| s.line.as_ref()),
+                ) {
+                    let area = status_chunks[2];
+                    Paragraph::new(
+                        [Text::styled(
+                            truncate_with_ellipsis(line, area.width as usize),
+                            Style::default().modifier(Modifier::DIM),
+                        )]
+                        .iter(),
+                    )
+                    .render(&mut f, area);
+                }
+
+                if let Some(progress) = analyzer.progress() {
+                    let max = progress.max;
+                    let speed = progress.rate;
+                    let eta = progress
+                        .eta
+                        .map(|eta| format_duration(Duration::from_secs(eta as u64)));
+
+                    let mut fragments = vec![Text::styled(
+                        unit.format_value(progress.value, self.opt.precision),
+                        Style::default(),
+                    )];
+                    if let Some(max) = max {
+                        fragments.push(Text::styled("/", Style::default().fg(theme.border)));
+                        fragments.push(Text::styled(
+                            unit.format_value(max, self.opt.precision),
+                            Style::default(),
+                        ));
+                    }
+                    fragments.push(Text::raw("\n\n"));
+                    if let Some(recent) = progress.recent_rate {
+                        // Compare the recent rate against the average
+                        let (arrow, color) = if recent > speed {
+                            (" ↑", theme.line)
+                        } else if recent < speed {
+                            (" ↓", theme.error)
+                        } else {
+                            ("", Color::Reset)
+                        };
+                        fragments.extend(vec![
+                            Text::styled("Rate ", Style::default().fg(theme.border)),
+                            Text::styled(rate_format.number(recent), Style::default()),
+                            Text::styled(rate_format.suffix(), Style::default().fg(theme.border)),
+                            Text::styled(arrow, Style::default().fg(color)),
+                            Text::raw("\n"),
+                        ]);
+                    }
+                    let unknown = if progress.warming_up {
+                        "measuring…"
+                    } else {
+                        "(unknown)"
+                    };
+                    fragments.extend(vec![
+                        Text::styled("Avg  ", Style::default().fg(theme.border)),
                         if speed > 0.0 {
-                            Text::styled(format!("{:.04e}", speed), Style::default())
+                            Text::styled(rate_format.number(speed), Style::default())
                         } else {
-                            Text::styled("(unknown)", Style::default().fg(Color::DarkGray))
+                            Text::styled(unknown, Style::default().fg(theme.border))
                         },
                         if speed > 0.0 {
-                            Text::styled("/sec", Style::default().fg(Color::DarkGray))
+                            Text::styled(rate_format.suffix(), Style::default().fg(theme.border))
                         } else {
-                            Text::styled("", Style::default().fg(Color::DarkGray))
+                            Text::styled("", Style::default().fg(theme.border))
                         },
-                        Text::styled("\n\n", Style::default()),
-                        Text::styled("ETA ", Style::default().fg(Color::DarkGray)),
-                        if let Some(eta) = eta {
-                            Text::styled(format!("{}", eta), Style::default())
+                    ]);
+                    if let Some(project) = self.opt.project {
+                        // The value projected at the current average rate
+                        let projected = Some(progress.value + speed * project.as_secs_f64())
+                            .filter(|x| x.is_finite());
+                        fragments.extend(vec![
+                            Text::raw("\n"),
+                            Text::styled(
+                                format!("In {} ", format_duration(project)),
+                                Style::default().fg(theme.border),
+                            ),
+                            Text::raw(projected.map_or_else(
+                                || "—".to_owned(),
+                                |x| unit.format_value(x, self.opt.precision),
+                            )),
+                        ]);
+                    }
+                    if max.is_some() {
+                        fragments.extend(vec![
+                            Text::styled("\n\n", Style::default()),
+                            Text::styled("ETA ", Style::default().fg(theme.border)),
+                            if let Some(eta) = eta {
+                                Text::styled(format!("{}", eta), Style::default())
+                            } else {
+                                Text::styled(unknown, Style::default().fg(theme.border))
+                            },
+                        ]);
+                        if let Some((earliest, latest)) =
+                            progress.eta_range.filter(|&(e, l)| e as u64 != l as u64)
+                        {
+                            // The confidence interval below the ETA
+                            let format = |eta: f64| {
+                                if eta.is_finite() {
+                                    format_duration(Duration::from_secs(eta as u64)).to_string()
+                                } else {
+                                    "∞".to_owned()
+                                }
+                            };
+                            fragments.push(Text::styled(
+                                format!("\n    {} – {}", format(earliest), format(latest)),
+                                Style::default().fg(theme.border),
+                            ));
+                        }
+                    }
+
+                    Paragraph::new(fragments.iter()).render(&mut f, status_chunks[1]);
+
+                    if let (Some(ratio), false) = (progress.ratio(), self.opt.no_gauge) {
+                        let label = if ratio > 1.0 {
+                            ">100%".to_owned()
                         } else {
-                            Text::styled("(unknown)", Style::default().fg(Color::DarkGray))
-                        },
-                    ]
-                    .iter(),
-                )
-                .render(&mut f, status_chunks[0]);
+                            format!("{}%", (ratio.max(0.0) * 100.0).round())
+                        };
+
+                        // `Gauge::ratio` panics if the ratio is out of range
+                        let ratio = if ratio.is_finite() {
+                            ratio.clamp(0.0, 1.0)
+                        } else {
+                            0.0
+                        };
+
+                        // Indicate whether the ETA exceeds the thresholds
+                        let exceeds = |threshold: Option<Duration>| {
+                            threshold.map_or(false, |threshold| {
+                                progress
+                                    .eta
+                                    .map_or(false, |eta| eta >= threshold.as_secs_f64())
+                            })
+                        };
+                        let color = if progress.eta.is_none()
+                            || (self.opt.eta_warn.is_none() && self.opt.eta_crit.is_none())
+                        {
+                            theme.gauge
+                        } else if exceeds(self.opt.eta_crit) {
+                            theme.error
+                        } else if exceeds(self.opt.eta_warn) {
+                            theme.warning
+                        } else {
+                            theme.line
+                        };
 
-                Gauge::default()
-                    .ratio(front.1 / max)
-                    .style(Style::default().fg(Color::White).bg(Color::Black))
+                        Gauge::default()
+                            .ratio(ratio)
+                            .label(&label)
+                            .style(Style::default().fg(color).bg(theme.gauge_background))
+                            .render(&mut f, status_chunks[4]);
+                    }
+
+                    // The current rate relative to the peak rate
+                    let peak_rate = analyzer.peak_rate().filter(|&peak| peak > 0.0);
+                    if let (Some(recent), Some(peak), false) =
+                        (progress.recent_rate, peak_rate, self.opt.no_gauge)
+                    {
+                        let ratio = (recent / peak).clamp(0.0, 1.0);
+                        let label = format!("{:.0}% of peak rate", ratio * 100.0);
+                        Gauge::default()
+                            .ratio(ratio)
+                            .label(&label)
+                            .style(Style::default().fg(theme.line).bg(theme.gauge_background))
+                            .render(&mut f, status_chunks[3]);
+                    }
+                } else {
+                    Paragraph::new(
+                        [Text::styled(
+                            "Waiting for more data...",
+                            Style::default().fg(theme.border),
+                        )]
+                        .iter(),
+                    )
                     .render(&mut f, status_chunks[1]);
-            } else {
-                Paragraph::new(
-                    [Text::styled(
-                        "Waiting for more data...",
-                        Style::default().fg(Color::DarkGray),
-                    )]
-                    .iter(),
-                )
-                .render(&mut f, status_chunks[0]);
+                }
             }
+            let diagnostics: Vec<String> = analyzer.diagnostics.iter().cloned().collect();
+            let parse_errors = analyzer
+                .parse_errors()
+                .map(|(count, last)| (count, last.to_owned()));
 
             drop(analyzer);
 
             // ---------------------------------------------------------------
             //  Output
+            let split = self.output_split;
             let out_chunks = Layout::default()
                 .direction(Direction::Horizontal)
                 .margin(0)
                 .constraints(
                     [
-                        Constraint::Ratio(2, 5),
-                        Constraint::Ratio(2, 5),
+                        // The output panes take up 4/5 of the width in total
+                        Constraint::Ratio(4 * split.stdout, 5 * (split.stdout + split.stderr)),
+                        Constraint::Ratio(4 * split.stderr, 5 * (split.stdout + split.stderr)),
                         Constraint::Min(20),
                     ]
                     .as_ref(),
@@ -235,44 +1088,182 @@ impl AppState {
             let out_chunks_merged = out_chunks[0].union(out_chunks[1]);
 
             let last_output = self.worker.last_output.lock().unwrap();
+            let slowest_outputs = self.worker.slowest_outputs.lock().unwrap();
+            let viewed_run = self
+                .viewed_run
+                .and_then(|i| Some((i, slowest_outputs.get(i)?)));
+            let output = match viewed_run {
+                Some((_, output)) => Some(Ok(output)),
+                None => last_output.as_ref().map(|output| output.as_ref()),
+            };
 
             let mut status_fragments;
 
-            match &*last_output {
+            match output {
                 Some(Ok(output)) => {
-                    status_fragments = vec![Text::styled(
-                        format!("The command exited with {}.", output.status),
-                        Style::default(),
-                    )];
+                    status_fragments = Vec::new();
+                    if let Some((i, _)) = viewed_run {
+                        status_fragments.push(Text::styled(
+                            format!(
+                                "Viewing the slowest run #{} of {}, started at {} and \
+                                 took {:.3}s.\n\n",
+                                i + 1,
+                                slowest_outputs.len(),
+                                humantime::format_rfc3339_seconds(output.start_time),
+                                output.elapsed().as_secs_f64(),
+                            ),
+                            Style::default().fg(theme.accent),
+                        ));
+                    }
+                    let (status_text, status_color) = if self.opt.watch_file.is_some() {
+                        ("The file was read.".to_owned(), Color::Reset)
+                    } else if self.opt.reads_stdin() {
+                        ("Reading stdin.".to_owned(), Color::Reset)
+                    } else if let Some(status) = output.status {
+                        if output.success == Some(true) {
+                            (format!("The command succeeded ({}).", status), theme.line)
+                        } else {
+                            (format!("The command failed ({}).", status), theme.error)
+                        }
+                    } else {
+                        ("The command is running.".to_owned(), Color::Reset)
+                    };
+                    status_fragments
+                        .push(Text::styled(status_text, Style::default().fg(status_color)));
 
-                    let stdout = &output.stdout;
-                    let stderr = &output.stderr;
+                    let (mut stdout_text, mut stderr_text): (Cow<str>, Cow<str>) =
+                        if self.opt.encoding == runner::Encoding::RawHex {
+                            (
+                                hex_dump(&output.stdout_raw).into(),
+                                hex_dump(&output.stderr_raw).into(),
+                            )
+                        } else {
+                            ((&output.stdout).into(), (&output.stderr).into())
+                        };
+                    if output.stdout_truncated {
+                        stdout_text = format!("{}\n{}", TRUNCATED_MARKER, stdout_text).into();
+                    }
+                    if output.stderr_truncated {
+                        stderr_text = format!("{}\n{}", TRUNCATED_MARKER, stderr_text).into();
+                    }
+                    let stdout: &str = &stdout_text;
+                    let stderr: &str = &stderr_text;
 
                     let stdout_sty = Style::default();
-                    let stderr_sty = Style::default().fg(Color::Yellow);
+                    let stderr_sty = Style::default().fg(theme.stderr);
 
-                    // Collapse a pane if empty to make a room for the other one
-                    let collapse_mode = match (stdout.is_empty(), stderr.is_empty()) {
-                        (_, true) => Some((b_stdout, stdout, stdout_sty)),
-                        (true, false) => Some((b_stderr, stderr, stderr_sty)),
-                        _ => None,
+                    let interpret_ansi =
+                        !self.opt.no_ansi && self.opt.encoding != runner::Encoding::RawHex;
+
+                    // Returns `(styled_text, plain_text)`
+                    let render_text = |text, style| {
+                        if interpret_ansi {
+                            (ansi::to_styled_text(text, style), ansi::strip(text).into())
+                        } else {
+                            (vec![Text::styled(text, style)], Cow::Borrowed(text))
+                        }
                     };
 
-                    if let Some((block, text, style)) = collapse_mode {
-                        Paragraph::new([Text::styled(text, style)].iter())
-                            .block(block)
-                            .wrap(true)
-                            .render(&mut f, out_chunks_merged);
+                    // (pane, block, styled_text, plain_text, area)
+                    let panes = if self.opt.interleave
+                        && self.opt.encoding != runner::Encoding::RawHex
+                    {
+                        let b_output = Block::default()
+                            .title("output")
+                            .title_style(title_style)
+                            .border_style(border_style)
+                            .borders(Borders::RIGHT);
+
+                        let mut styled_text = Vec::new();
+                        let mut plain_text = String::new();
+                        for line in output.lines.iter() {
+                            let prefix = format!("[{:8.3}s] ", line.time.as_secs_f64());
+                            let style = match line.stream {
+                                runner::Stream::Stdout => stdout_sty,
+                                runner::Stream::Stderr => stderr_sty,
+                            };
+                            let (line_styled, line_plain) = render_text(&line.text, style);
+
+                            plain_text.push_str(&prefix);
+                            plain_text.push_str(&line_plain);
+                            plain_text.push('\n');
+                            styled_text
+                                .push(Text::styled(prefix, Style::default().fg(theme.border)));
+                            styled_text.extend(line_styled);
+                            styled_text.push(Text::raw("\n"));
+                        }
+
+                        vec![(
+                            Pane::Stdout,
+                            b_output,
+                            styled_text,
+                            Cow::Owned(plain_text),
+                            out_chunks_merged,
+                        )]
                     } else {
-                        Paragraph::new([Text::styled(stdout, stdout_sty)].iter())
-                            .block(b_stdout)
-                            .wrap(true)
-                            .render(&mut f, out_chunks[0]);
+                        match (stdout.is_empty(), stderr.is_empty() || !self.show_stderr) {
+                            // Collapse a pane if empty (or hidden) to make a room for the
+                            // other one
+                            (_, true) => {
+                                let (styled, plain) = render_text(stdout, stdout_sty);
+                                vec![(Pane::Stdout, b_stdout, styled, plain, out_chunks_merged)]
+                            }
+                            (true, false) => {
+                                let (styled, plain) = render_text(stderr, stderr_sty);
+                                vec![(Pane::Stderr, b_stderr, styled, plain, out_chunks_merged)]
+                            }
+                            _ => {
+                                let (stdout_styled, stdout_plain) = render_text(stdout, stdout_sty);
+                                let (stderr_styled, stderr_plain) = render_text(stderr, stderr_sty);
+                                vec![
+                                    (
+                                        Pane::Stdout,
+                                        b_stdout,
+                                        stdout_styled,
+                                        stdout_plain,
+                                        out_chunks[0],
+                                    ),
+                                    (
+                                        Pane::Stderr,
+                                        b_stderr,
+                                        stderr_styled,
+                                        stderr_plain,
+                                        out_chunks[1],
+                                    ),
+                                ]
+                            }
+                        }
+                    };
 
-                        Paragraph::new([Text::styled(stderr, stderr_sty)].iter())
-                            .block(b_stderr)
+                    let num_panes = panes.len();
+                    if num_panes == 1 {
+                        // The only visible pane receives the scroll commands
+                        self.focused_pane = panes[0].0;
+                    }
+
+                    for (pane, block, styled_text, plain_text, area) in panes {
+                        let block = if num_panes > 1 && pane == self.focused_pane {
+                            block.title_style(title_style.fg(theme.accent))
+                        } else {
+                            block
+                        };
+
+                        // Clamp the scroll offset so that the last line stays
+                        // at the bottom at most
+                        let inner = block.inner(area);
+                        if pane == self.focused_pane {
+                            self.output_pane_height = inner.height;
+                        }
+                        let max_scroll = wrapped_line_count(&plain_text, inner.width)
+                            .saturating_sub(inner.height as usize);
+                        let scroll = &mut self.output_scroll[pane as usize];
+                        *scroll = max_scroll.min(*scroll as usize).min(u16::MAX as usize) as u16;
+
+                        Paragraph::new(styled_text.iter())
+                            .block(block)
                             .wrap(true)
-                            .render(&mut f, out_chunks[1]);
+                            .scroll(*scroll)
+                            .render(&mut f, area);
                     }
                 }
                 Some(Err(e)) => {
@@ -280,8 +1271,14 @@ impl AppState {
 
                     status_fragments = vec![
                         Text::styled(
-                            "Failed to run the command.\n\n",
-                            Style::default().fg(Color::Red),
+                            if self.opt.watch_file.is_some() {
+                                "Failed to read the file.\n\n"
+                            } else if self.opt.reads_stdin() {
+                                "Failed to read stdin.\n\n"
+                            } else {
+                                "Failed to run the command.\n\n"
+                            },
+                            Style::default().fg(theme.error),
                         ),
                         Text::styled(format!("{}", e), Style::default()),
                     ];
@@ -294,11 +1291,41 @@ impl AppState {
                 }
             }
 
+            if let Some(notification) = self.notification() {
+                status_fragments.push(Text::raw("\n\n"));
+                status_fragments.push(Text::styled(
+                    notification,
+                    Style::default().fg(theme.accent),
+                ));
+            }
+
+            if let Some((count, last)) = parse_errors {
+                status_fragments.push(Text::raw("\n\n"));
+                status_fragments.push(Text::styled(
+                    format!(
+                        "{} parse error{} (last: {:?})",
+                        count,
+                        if count == 1 { "" } else { "s" },
+                        last
+                    ),
+                    Style::default().fg(theme.warning),
+                ));
+            }
+
+            if !diagnostics.is_empty() {
+                status_fragments.push(Text::raw("\n\n"));
+                status_fragments.push(Text::styled(
+                    "Diagnostics",
+                    Style::default().fg(theme.border),
+                ));
+                for diagnostic in diagnostics.iter() {
+                    status_fragments.push(Text::raw("\n"));
+                    status_fragments.push(Text::raw(diagnostic));
+                }
+            }
+
             status_fragments.push(Text::raw("\n\n"));
-            status_fragments.push(Text::styled(
-                "Command\n",
-                Style::default().fg(Color::DarkGray),
-            ));
+            status_fragments.push(Text::styled("Command\n", Style::default().fg(theme.border)));
             status_fragments.push(Text::styled(&self.cmd_string, Style::default()));
 
             Paragraph::new(status_fragments.iter())
@@ -310,87 +1337,788 @@ impl AppState {
             // Help
 
             if self.show_help {
-                draw_help(&mut f);
+                let quit_keys = self.quit_keys_label();
+                draw_help(&mut f, &theme, &quit_keys, &mut self.help_scroll);
             }
         })?;
         Ok(())
     }
 }
 
-/// Given a 2D data series, produce another series representing the increase
-/// rate of the given series.
-fn analyze_rate(data: impl Iterator<Item = (f64, f64)>) -> impl Iterator<Item = (f64, f64)> {
-    data.scan(None, |st, (t, v)| {
-        if let Some((last_t, last_v)) = *st {
-            if v == last_v {
-                Some(None)
-            } else {
-                let ret = (last_t, (v - last_v) / (t - last_t));
-                *st = Some((t, v));
-                Some(Some(ret))
+/// `(label, color, data)`, where `data` is `(time, y)` pairs.
+type Series<'a> = (&'a str, Color, &'a [(f64, f64)]);
+
+/// A chart of quantities over the time axis.
+struct TimeSeriesChart<'a> {
+    title: &'a str,
+    /// The time axis spans `[-time_scale, 0]` in seconds.
+    time_scale: f64,
+    /// How far (in seconds) the right end of the time axis is from the latest
+    /// sample.
+    pan: f64,
+    /// Label the right end of the time axis "exit" instead of "now".
+    exited: bool,
+    y_title: &'a str,
+    series: &'a [Series<'a>],
+    /// `(time, text)` pairs marked with vertical lines.
+    annotations: &'a [(f64, &'a str)],
+    /// Plot `log10(y)`. Non-positive values are omitted.
+    log_scale: bool,
+    /// Draw dotted lines at the intermediate ticks.
+    grid: bool,
+    /// Format a y value given the magnitude of the axis.
+    format_y: &'a dyn Fn(f64, f64) -> String,
+}
+
+impl TimeSeriesChart<'_> {
+    fn draw(&self, f: &mut Frame<impl Backend>, area: Rect, theme: &Theme, title_style: Style) {
+        let block = Block::default().title(self.title).title_style(title_style);
+
+        let log_scale = self.log_scale;
+        let series: Vec<_> = self
+            .series
+            .iter()
+            .map(|&(label, color, data)| {
+                let data = if log_scale {
+                    data.iter()
+                        .filter(|&&(_, v)| v > 0.0)
+                        .map(|&(t, v)| (t, v.log10()))
+                        .collect()
+                } else {
+                    data.to_vec()
+                };
+                (label, color, data)
+            })
+            .collect();
+
+        // Scale the number of ticks to the room. The x axis takes up two rows,
+        // and the labels of the x axis are wider.
+        let chart_inner = block.inner(area);
+        let num_x_ticks = tick_count(chart_inner.width, 20);
+        let num_y_ticks = tick_count(chart_inner.height.saturating_sub(2), 3);
+        let x_ticks = ticks([-self.time_scale, 0.0], num_x_ticks);
+
+        let values = series.iter().flat_map(|s| s.2.iter().map(|s| s.1));
+        let (value_range, y_ticks) = if log_scale {
+            log_value_range(values, num_y_ticks)
+        } else {
+            let range = value_range(values);
+            (range, ticks(range, num_y_ticks))
+        };
+
+        let pan = self.pan;
+        let x_labels: Vec<_> = x_ticks
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| {
+                let ago = format_duration(Duration::from_secs((pan - t).round() as u64));
+                if i == 0 {
+                    format!("{} ago", ago)
+                } else if i + 1 < num_x_ticks || pan > 0.0 {
+                    ago.to_string()
+                } else if self.exited {
+                    "exit".to_owned()
+                } else {
+                    "now".to_owned()
+                }
+            })
+            .collect();
+        let y_labels: Vec<_> = y_ticks
+            .iter()
+            .map(|&v| {
+                if log_scale {
+                    // The ticks span orders of magnitude, so each one gets its
+                    // own magnitude
+                    let v = 10f64.powf(v);
+                    (self.format_y)(v, v)
+                } else {
+                    (self.format_y)(v, value_range[0].abs().max(value_range[1].abs()))
+                }
+            })
+            .collect();
+
+        // Dotted lines at the intermediate ticks
+        let data_grid: Vec<_> = if self.grid {
+            let (num_x_points, num_y_points) = (chart_inner.width / 2, chart_inner.height * 2);
+            let inner_ticks = |ticks: &[f64]| ticks[1..ticks.len() - 1].to_vec();
+            let horizontal = inner_ticks(&y_ticks).into_iter().flat_map(|v| {
+                ticks([-self.time_scale, 0.0], num_x_points as usize)
+                    .into_iter()
+                    .map(move |t| (t, v))
+            });
+            let vertical = inner_ticks(&x_ticks).into_iter().flat_map(|t| {
+                ticks(value_range, num_y_points as usize)
+                    .into_iter()
+                    .map(move |v| (t, v))
+            });
+            horizontal.chain(vertical).collect()
+        } else {
+            Vec::new()
+        };
+
+        // Vertical lines at the annotations
+        let data_annotation: Vec<_> = self
+            .annotations
+            .iter()
+            .flat_map(|&(t, _)| {
+                ticks(value_range, chart_inner.height as usize * 4)
+                    .into_iter()
+                    .map(move |v| (t, v))
+            })
+            .collect();
+
+        // (label, color, data)
+        let mut series: Vec<_> = series
+            .iter()
+            .map(|(label, color, data)| (*label, *color, &data[..]))
+            .collect();
+        if !self.annotations.is_empty() {
+            series.push(("Annotation", theme.accent, &data_annotation[..]));
+        }
+
+        // The grid is drawn first so that the data overlays it
+        let datasets: Vec<_> = Some(("Grid", theme.border, &data_grid[..]))
+            .filter(|_| self.grid)
+            .iter()
+            .chain(&series)
+            .map(|&(_, color, data)| {
+                Dataset::default()
+                    .marker(Marker::Braille)
+                    .style(Style::default().fg(color))
+                    .data(data)
+            })
+            .collect();
+
+        let y_title = if log_scale {
+            format!("{} (log)", self.y_title)
+        } else {
+            self.y_title.to_owned()
+        };
+
+        let x_bounds = [-self.time_scale - 0.1, 0.1];
+        Chart::default()
+            .block(block)
+            .x_axis(
+                Axis::default()
+                    .title("Time")
+                    .bounds(x_bounds)
+                    .labels(&x_labels),
+            )
+            .y_axis(
+                Axis::default()
+                    .title(&y_title)
+                    .bounds(value_range)
+                    .labels(&y_labels),
+            )
+            .datasets(&datasets)
+            .render(f, area);
+
+        // Label the annotations at the top of the graph area, which is placed
+        // after the widest label by `Chart`
+        let label_width = y_labels
+            .iter()
+            .chain(x_labels.first())
+            .map(|label| label.chars().count())
+            .max()
+            .unwrap_or(0) as u16;
+        let graph_left = chart_inner.x + label_width + 1;
+        let graph_width = chart_inner.right().saturating_sub(graph_left);
+        for &(t, text) in self.annotations.iter() {
+            // Skip the annotations outside the viewport before converting
+            // the position to a cell
+            let offset = ((t - x_bounds[0]) / (x_bounds[1] - x_bounds[0])
+                * graph_width.saturating_sub(1) as f64)
+                .round();
+            if !(0.0..graph_width as f64).contains(&offset) {
+                continue;
             }
+            let x = graph_left + offset as u16;
+            Paragraph::new([Text::styled(text, Style::default().fg(theme.accent))].iter())
+                .render(f, Rect::new(x, chart_inner.y, chart_inner.right() - x, 1));
+        }
+
+        if series.len() > 1 {
+            let legend: Vec<_> = series
+                .iter()
+                .map(|&(label, color, _)| (label, color))
+                .collect();
+            draw_legend(f, chart_inner, &legend, theme);
+        }
+    }
+}
+
+/// Stack the components of `(time, components)` points. The `i`-th returned
+/// series is the sum of the first `i + 1` components at each point. A missing
+/// component keeps its last value, or zero if there is none.
+fn stack_series(points: &[(f64, Vec<Option<f64>>)], num_components: usize) -> Vec<Vec<(f64, f64)>> {
+    let mut last = vec![0.0; num_components];
+    let mut stacked = vec![Vec::with_capacity(points.len()); num_components];
+    for (t, components) in points {
+        let mut total = 0.0;
+        for (i, out) in stacked.iter_mut().enumerate() {
+            if let Some(v) = components.get(i).copied().flatten() {
+                last[i] = v;
+            }
+            total += last[i];
+            out.push((*t, total));
+        }
+    }
+    stacked
+}
+
+/// Draw a legend mapping each label to its color at the top-right corner of
+/// `area`. Nothing is drawn if it doesn't fit.
+fn draw_legend(f: &mut Frame<impl Backend>, area: Rect, entries: &[(&str, Color)], theme: &Theme) {
+    let label_width = entries
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0) as u16;
+
+    // "─ label" surrounded by borders
+    let width = label_width + 4;
+    let height = entries.len() as u16 + 2;
+    if width > area.width || height > area.height {
+        return;
+    }
+
+    let rect = Rect {
+        x: area.right() - width,
+        y: area.y,
+        width,
+        height,
+    };
+
+    // `Paragraph` doesn't clear the cells, so the labels are padded to hide
+    // the chart underneath
+    let mut fragments = Vec::with_capacity(entries.len() * 2);
+    for (i, &(label, color)) in entries.iter().enumerate() {
+        if i > 0 {
+            fragments.push(Text::raw("\n"));
+        }
+        fragments.push(Text::styled("─ ", Style::default().fg(color)));
+        fragments.push(Text::raw(format!("{:1$}", label, label_width as usize)));
+    }
+
+    Paragraph::new(fragments.iter())
+        .block(
+            Block::default()
+                .border_style(Style::default().fg(theme.border))
+                .borders(Borders::ALL),
+        )
+        .render(f, rect);
+}
+
+/// Draw the distribution of the rate values as a histogram.
+fn draw_rate_histogram(
+    f: &mut Frame<impl Backend>,
+    area: Rect,
+    data_rate: &[(f64, f64)],
+    num_bins: usize,
+    rate_format: &RateFormat,
+    theme: &Theme,
+) {
+    let block = Block::default()
+        .title("Rate Distribution")
+        .title_style(Style::default().fg(theme.border));
+
+    let (range, counts) = histogram(data_rate.iter().map(|s| s.1), num_bins.max(1));
+
+    // Label each bar with the lower bound of its bin
+    let bin_width = (range[1] - range[0]) / counts.len() as f64;
+    let labels: Vec<String> = (0..counts.len())
+        .map(|i| rate_format.number(range[0] + bin_width * i as f64))
+        .collect();
+    let data: Vec<(&str, u64)> = labels
+        .iter()
+        .map(String::as_str)
+        .zip(counts.iter().cloned())
+        .collect();
+
+    let inner_width = block.inner(area).width;
+    let bar_width = (inner_width / counts.len() as u16).saturating_sub(1).max(1);
+
+    BarChart::default()
+        .block(block)
+        .data(&data)
+        .bar_width(bar_width)
+        .bar_gap(1)
+        .style(Style::default().fg(theme.line))
+        .value_style(Style::default().fg(theme.line).modifier(Modifier::REVERSED))
+        .render(f, area);
+}
+
+/// Count the finite values falling into each of `num_bins` equal-width bins.
+/// Returns the range covered by the bins and the counts.
+fn histogram(values: impl Iterator<Item = f64> + Clone, num_bins: usize) -> ([f64; 2], Vec<u64>) {
+    let values = values.filter(|x| x.is_finite());
+    let range = values.clone().fold([f64::NAN, f64::NAN], |[min, max], x| {
+        [min.min(x), max.max(x)]
+    });
+
+    let mut counts = vec![0; num_bins];
+    if range[0].is_nan() {
+        return ([0.0, 1.0], counts);
+    }
+
+    let width = range[1] - range[0];
+    for x in values {
+        let i = if width > 0.0 {
+            ((x - range[0]) / width * num_bins as f64) as usize
         } else {
-            *st = Some((t, v));
-            Some(None)
+            0
+        };
+        counts[i.min(num_bins - 1)] += 1;
+    }
+
+    (range, counts)
+}
+
+/// Shorten `s` to at most `width` characters, replacing the removed part with
+/// an ellipsis.
+fn truncate_with_ellipsis(s: &str, width: usize) -> Cow<'_, str> {
+    if s.chars().count() <= width {
+        return s.into();
+    }
+
+    let mut out: String = s.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        out.push('…');
+    }
+    out.into()
+}
+
+/// Format bytes as a hex dump, 16 bytes per line.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 3);
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        out.push_str(&format!("{:08x} ", i * 16));
+        for b in chunk {
+            out.push_str(&format!(" {:02x}", b));
         }
-    })
-    .filter_map(|x| x)
-    .skip(1)
+        out.push('\n');
+    }
+    out
+}
+
+/// Compute the bounds of a chart's y axis that cover the given values with
+/// some margin. Non-finite values are ignored. Falls back to `[0, 1]` if
+/// there are no values to show.
+pub(crate) fn value_range(values: impl Iterator<Item = f64>) -> [f64; 2] {
+    let range = values
+        .filter(|x| x.is_finite())
+        .fold([f64::NAN, f64::NAN], |[min, max], x| {
+            [min.min(x), max.max(x)]
+        });
+
+    if range[0].is_nan() {
+        return [0.0, 1.0];
+    }
+
+    let width = range[1] - range[0];
+    let margin = if width > 0.0 {
+        width * 0.1
+    } else {
+        // All values are identical. Give the range a non-zero width.
+        (range[0].abs() * 0.1).max(1.0)
+    };
+    let mut value_range = [range[0] - margin, range[1] + margin];
+
+    if range[0] >= 0.0 {
+        value_range[0] = value_range[0].max(0.0);
+    }
+
+    value_range
+}
+
+/// Determine the range of a logarithmic axis from `log10` values and place
+/// `count` evenly spaced ticks. The range is extended to whole decades so that
+/// the ticks fall on powers of ten.
+fn log_value_range(values: impl Iterator<Item = f64>, count: usize) -> ([f64; 2], Vec<f64>) {
+    let range = values
+        .filter(|x| x.is_finite())
+        .fold([f64::NAN, f64::NAN], |[min, max], x| {
+            [min.min(x), max.max(x)]
+        });
+    let (low, high) = if range[0].is_nan() {
+        (0.0, 1.0)
+    } else {
+        (
+            range[0].floor(),
+            range[1].ceil().max(range[0].floor() + 1.0),
+        )
+    };
+
+    // The number of decades between ticks
+    let num_steps = count.saturating_sub(1).max(1) as f64;
+    let step = ((high - low) / num_steps).ceil();
+    let high = low + step * num_steps;
+
+    ([low, high], ticks([low, high], count))
 }
 
-lazy_static::lazy_static! {
-    static ref HELP_DATA: (Vec<Text<'static>>, u16, u16) = {
-        const TEXT: &str = "\x02        h:\x01 Toggle this help window\n\
-                            \x02 ESC q ^C:\x01 Quit";
-        let width: usize = TEXT.lines().map(|line| line.bytes().filter(|&b| b >= 0x20).count()).max().unwrap();
-        let height = TEXT.lines().count();
-
-        let mut fragments = Vec::new();
-
-        let mut text = TEXT;
-        let mut style = Style::default();
-        loop {
-            if let Some((k, b)) = text.bytes().enumerate().find(|&(_, b)| b < 0x08) {
-                fragments.push(Text::styled(&text[..k], style));
-                match b {
-                    0x01 => style = Style::default(),
-                    0x02 => style = Style::default().fg(Color::LightCyan),
-                    _ => unreachable!(),
+/// SI prefixes applied to dimensionless rates.
+const SI_PREFIXES: &[(f64, &str)] = &[(1e12, "T"), (1e9, "G"), (1e6, "M"), (1e3, "k")];
+
+/// Formats rates (per second) using a time scale and an SI prefix chosen from
+/// a reference magnitude, so that the numbers in a redraw share them.
+struct RateFormat<'a> {
+    unit: &'a Unit,
+    /// The length of the time scale in seconds and its name.
+    per: (f64, &'static str),
+    /// The divisor and the SI prefix. Only used for `Unit::None` and
+    /// `Unit::Custom`.
+    prefix: (f64, &'static str),
+    /// Use `Unit::format_approx` without rescaling.
+    sci: bool,
+    /// The number of decimal places. Chosen from the magnitude if `None`.
+    precision: Option<usize>,
+}
+
+impl<'a> RateFormat<'a> {
+    fn new(unit: &'a Unit, reference: f64, sci: bool, precision: Option<usize>) -> Self {
+        let reference = if reference.is_finite() {
+            reference.abs()
+        } else {
+            0.0
+        };
+
+        // Use per-minute figures for slow progress
+        let per = if !sci && reference > 0.0 && reference < 1.0 {
+            (60.0, "min")
+        } else {
+            (1.0, "s")
+        };
+
+        let prefix = SI_PREFIXES
+            .iter()
+            .find(|&&(divisor, _)| reference * per.0 >= divisor)
+            .filter(|_| !sci)
+            .cloned()
+            .unwrap_or((1.0, ""));
+
+        Self {
+            unit,
+            per,
+            prefix,
+            sci,
+            precision,
+        }
+    }
+
+    /// Format a rate without the time scale, e.g., `1.2k`.
+    fn number(&self, rate: f64) -> String {
+        if self.sci {
+            return self.unit.format_approx(rate, self.precision);
+        }
+
+        let x = rate * self.per.0;
+        match self.unit {
+            Unit::None | Unit::Custom(_) => {
+                let scaled = x / self.prefix.0;
+                let precision = self.precision.unwrap_or(if scaled.abs() >= 100.0 {
+                    0
+                } else if scaled.abs() >= 10.0 {
+                    1
+                } else {
+                    2
+                });
+                let number = format!("{:.*}{}", precision, scaled, self.prefix.1);
+                if let Unit::Custom(suffix) = self.unit {
+                    format!("{} {}", number, suffix)
+                } else {
+                    number
                 }
-                text = &text[k + 1..];
-            } else {
-                fragments.push(Text::styled(text, style));
-                break;
             }
+            _ => self.unit.format_approx(x, self.precision),
         }
+    }
 
-        (fragments, width as u16, height as u16)
-    };
+    /// Get the time scale suffix, e.g., `/s`.
+    fn suffix(&self) -> String {
+        format!("/{}", self.per.1)
+    }
+
+    /// Format a rate with the time scale, e.g., `1.2k/s`.
+    fn format(&self, rate: f64) -> String {
+        self.number(rate) + &self.suffix()
+    }
+}
+
+/// Choose the number of ticks (2–5, including the both ends) for an axis
+/// `length` cells long, placing them at least `spacing` cells apart.
+fn tick_count(length: u16, spacing: u16) -> usize {
+    (length / spacing + 1).clamp(2, 5) as usize
+}
+
+/// Get `count` evenly spaced values from `bounds[0]` to `bounds[1]`.
+pub(crate) fn ticks(bounds: [f64; 2], count: usize) -> Vec<f64> {
+    let last = count.saturating_sub(1).max(1) as f64;
+    (0..count)
+        .map(|i| bounds[0] + (bounds[1] - bounds[0]) * i as f64 / last)
+        .collect()
 }
 
-fn draw_help(f: &mut Frame<impl Backend>) {
+/// Estimate the number of lines `text` occupies when wrapped at `width`.
+fn wrapped_line_count(text: &str, width: u16) -> usize {
+    let width = width.max(1) as usize;
+    text.lines()
+        .map(|line| ((line.chars().count() + width - 1) / width).max(1))
+        .sum()
+}
+
+fn draw_too_small(f: &mut Frame<impl Backend>, theme: &Theme) {
+    let size = f.size();
+    Paragraph::new(
+        [Text::styled(
+            "Terminal too small",
+            Style::default().fg(theme.border),
+        )]
+        .iter(),
+    )
+    .wrap(true)
+    .render(f, size);
+}
+
+/// Draw the help window listing `keys::BINDINGS`. `quit_keys` describes the
+/// keys that quit the application, which are configurable. `scroll` is the
+/// offset (in lines) of the content, which is clamped here.
+fn draw_help(f: &mut Frame<impl Backend>, theme: &Theme, quit_keys: &str, scroll: &mut u16) {
     use std::cmp::min;
 
-    let (frags, width, height) = &*HELP_DATA;
+    // (key label, description)
+    let lines: Vec<(String, &str)> = keys::BINDINGS
+        .iter()
+        .map(|binding| (format!(" {:>8}:", binding.label), binding.description))
+        .chain(Some((format!(" {:>8}:", quit_keys), "Quit")))
+        .collect();
+
+    let width = lines
+        .iter()
+        .map(|(label, description)| label.chars().count() + 1 + description.chars().count())
+        .max()
+        .unwrap_or(0) as u16;
+    let height = lines.len() as u16;
 
     let size = f.size();
 
-    let width = min(*width, size.width - 5);
-    let height = min(*height, size.height - 5);
+    // The box needs a margin of at least 5 cells in each direction
+    if size.width < 5 || size.height < 5 {
+        return;
+    }
+
+    let width = min(width, size.width - 5);
+    let visible_height = min(height, size.height - 5);
 
     let rect = Rect {
         x: size.width - width - 4,
-        y: size.height - height - 3,
+        y: size.height - visible_height - 3,
         width: width + 3,
-        height: height + 2,
+        height: visible_height + 2,
+    };
+
+    *scroll = min(*scroll, height - visible_height);
+
+    let title = if height > visible_height {
+        "Help (UP/DOWN to scroll)"
+    } else {
+        "Help"
     };
 
+    let mut frags = Vec::new();
+    for (i, (label, description)) in lines.iter().enumerate() {
+        if i > 0 {
+            frags.push(Text::raw("\n"));
+        }
+        frags.push(Text::styled(label, Style::default().fg(theme.accent)));
+        // Padded to cover what's underneath
+        let width = width as usize - label.chars().count();
+        frags.push(Text::raw(format!(" {:<1$}", description, width)));
+    }
+
     Paragraph::new(frags.iter())
         .block(
             Block::default()
-                .title("Help")
-                .border_style(Style::default().fg(Color::LightCyan))
+                .title(title)
+                .border_style(Style::default().fg(theme.accent))
                 .borders(Borders::ALL),
         )
+        .scroll(*scroll)
         .render(f, rect);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis, analysis_options, AppEventSender, Opt, WorkerState};
+    use std::sync::{mpsc, Mutex};
+    use structopt::StructOpt;
+    use tui::backend::TestBackend;
+
+    fn test_app() -> AppState {
+        let opt = Opt::from_iter(&["pvfilt", "--show-match"]);
+        let analyzer = analysis::Analyzer::new(analysis_options(&opt));
+        let worker = WorkerState {
+            analyzer: Box::leak(Box::new(Mutex::new(analyzer))),
+            last_output: Box::leak(Box::new(Mutex::new(None))),
+            slowest_outputs: Box::leak(Box::new(Mutex::new(Vec::new()))),
+            rerun: mpsc::channel().0,
+            pause: Box::leak(Box::default()),
+        };
+        let (send, _) = mpsc::channel();
+        AppState::new(
+            worker,
+            Vec::new(),
+            AppEventSender(send),
+            "true".to_owned(),
+            opt,
+        )
+    }
+
+    #[test]
+    fn draw_tiny_terminal() {
+        let mut app = test_app();
+        let mut terminal = Terminal::new(TestBackend::new(10, 3)).unwrap();
+        app.draw(&mut terminal).unwrap();
+    }
+
+    #[test]
+    fn draw_small_terminals() {
+        let mut app = test_app();
+        app.show_help = true;
+
+        let now = Instant::now();
+        let mut analyzer = app.worker.analyzer.lock().unwrap();
+        for i in 0..3 {
+            analyzer.samples.push_back(analysis::Sample {
+                instant: now - Duration::from_secs(3 - i),
+                time: std::time::SystemTime::now(),
+                value: (i * i) as f64,
+                max: Some(10.0),
+                line: Some("progress".into()),
+                series: vec![Some(i as f64)],
+            });
+        }
+        drop(analyzer);
+
+        for width in 1..60 {
+            for height in 1..20 {
+                let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+                app.draw(&mut terminal).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn parse_output_split() {
+        assert_eq!(
+            "1:3".parse(),
+            Ok(OutputSplit {
+                stdout: 1,
+                stderr: 3
+            })
+        );
+        assert_eq!(
+            " 100 : 0 ".parse(),
+            Ok(OutputSplit {
+                stdout: 100,
+                stderr: 0
+            })
+        );
+        for s in &["0:0", "1", "1:x", "-1:1", "101:1", "2000000000:1"] {
+            assert!(s.parse::<OutputSplit>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn stack_series_carries_forward() {
+        let points = [
+            (0.0, vec![Some(30.0), Some(20.0), None]),
+            (1.0, vec![Some(40.0), None, Some(50.0)]),
+            (2.0, vec![Some(10.0), Some(5.0)]),
+        ];
+        assert_eq!(
+            stack_series(&points, 3),
+            [
+                vec![(0.0, 30.0), (1.0, 40.0), (2.0, 10.0)],
+                vec![(0.0, 50.0), (1.0, 60.0), (2.0, 15.0)],
+                vec![(0.0, 50.0), (1.0, 110.0), (2.0, 65.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn draw_offscreen_annotations() {
+        let mut app = test_app();
+
+        let now = Instant::now();
+        let mut analyzer = app.worker.analyzer.lock().unwrap();
+        for i in 0..3 {
+            analyzer.samples.push_back(analysis::Sample {
+                instant: now - Duration::from_secs(3 - i),
+                time: std::time::SystemTime::now(),
+                value: i as f64,
+                max: None,
+                line: None,
+                series: Vec::new(),
+            });
+        }
+        for &instant in &[
+            now - Duration::from_secs(3600),
+            now + Duration::from_secs(3600),
+        ] {
+            analyzer.annotations.push_back(analysis::Annotation {
+                instant,
+                text: "offscreen".to_owned(),
+            });
+        }
+        drop(analyzer);
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        app.draw(&mut terminal).unwrap();
+    }
+
+    #[test]
+    fn value_range_empty() {
+        assert_eq!(value_range(std::iter::empty()), [0.0, 1.0]);
+        assert_eq!(
+            value_range([f64::NAN, f64::INFINITY].iter().cloned()),
+            [0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn value_range_constant() {
+        let range = value_range([5.0, 5.0, 5.0].iter().cloned());
+        assert!(range[0] < 5.0 && range[1] > 5.0, "{:?}", range);
+
+        let range = value_range([0.0, 0.0].iter().cloned());
+        assert_eq!(range, [0.0, 1.0]);
+
+        let range = value_range([-3.0].iter().cloned());
+        assert!(range[0] < -3.0 && range[1] > -3.0, "{:?}", range);
+    }
+
+    #[test]
+    fn value_range_margin() {
+        assert_eq!(value_range([10.0, 20.0].iter().cloned()), [9.0, 21.0]);
+        assert_eq!(value_range([1.0, 11.0].iter().cloned()), [0.0, 12.0]);
+        assert_eq!(value_range([-1.0, 9.0].iter().cloned()), [-2.0, 10.0]);
+    }
+
+    #[test]
+    fn log_value_range_decades() {
+        // 2/s to 3000/s
+        let (range, ticks) = log_value_range([0.3, 3.5].iter().cloned(), 5);
+        assert_eq!(range, [0.0, 4.0]);
+        assert_eq!(ticks, [0.0, 1.0, 2.0, 3.0, 4.0]);
+
+        // Too many decades for the ticks
+        let (range, ticks) = log_value_range([-2.5, 5.5].iter().cloned(), 3);
+        assert_eq!(range, [-3.0, 7.0]);
+        assert_eq!(ticks, [-3.0, 2.0, 7.0]);
+
+        let (range, _) = log_value_range([2.0].iter().cloned(), 2);
+        assert_eq!(range, [2.0, 3.0]);
+    }
+}
@@ -0,0 +1,127 @@
+//! Serializes samples for use by other tools.
+use std::{
+    io::{self, Write},
+    time::UNIX_EPOCH,
+};
+
+use crate::analysis::{Progress, Sample};
+
+/// Write samples as CSV. `time` is the UNIX time in seconds, and `rate` is
+/// the increase rate (per second) since the previous sample.
+pub fn write_csv<'a>(
+    out: &mut impl Write,
+    samples: impl IntoIterator<Item = &'a Sample>,
+) -> io::Result<()> {
+    writeln!(out, "time,value,max,rate")?;
+
+    let mut last: Option<&Sample> = None;
+    for sample in samples {
+        let time = sample
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        write!(out, "{:.3},{},", time, sample.value)?;
+        if let Some(max) = sample.max {
+            write!(out, "{}", max)?;
+        }
+        write!(out, ",")?;
+        if let Some(last) = last {
+            let dt = sample.instant.duration_since(last.instant).as_secs_f64();
+            if dt > 0.0 {
+                write!(out, "{}", (sample.value - last.value) / dt)?;
+            }
+        }
+        writeln!(out)?;
+
+        last = Some(sample);
+    }
+
+    Ok(())
+}
+
+/// Write samples as whitespace-separated `elapsed value max` rows, e.g., for
+/// gnuplot. `elapsed` is the time in seconds since the first sample. A missing
+/// `max` is written as `NaN`.
+pub fn write_columns<'a>(
+    out: &mut impl Write,
+    samples: impl IntoIterator<Item = &'a Sample>,
+) -> io::Result<()> {
+    let mut first: Option<&Sample> = None;
+    for sample in samples {
+        let first = *first.get_or_insert(sample);
+        let elapsed = sample.instant.duration_since(first.instant).as_secs_f64();
+        writeln!(
+            out,
+            "{:.3} {} {}",
+            elapsed,
+            sample.value,
+            sample.max.unwrap_or(f64::NAN)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the current progress as a single-line JSON object:
+/// `{"value", "max", "pct", "rate", "eta_secs", "elapsed_secs"}`. Unknown
+/// quantities are written as `null`.
+pub fn write_progress_json(out: &mut impl Write, progress: &Progress) -> io::Result<()> {
+    writeln!(
+        out,
+        "{{\"value\":{},\"max\":{},\"pct\":{},\"rate\":{},\"eta_secs\":{},\"elapsed_secs\":{}}}",
+        json_number(Some(progress.value)),
+        json_number(progress.max),
+        json_number(progress.ratio().map(|ratio| ratio * 100.0)),
+        json_number(Some(progress.rate)),
+        json_number(progress.eta),
+        json_number(Some(progress.elapsed)),
+    )
+}
+
+/// Write a sample as a single-line JSON object:
+/// `{"time", "value", "max", "rate", "eta_secs"}`. `time` is the UNIX time in
+/// seconds. `rate` and `eta_secs` are taken from `progress`. Unknown
+/// quantities are written as `null`.
+pub fn write_sample_json(
+    out: &mut impl Write,
+    sample: &Sample,
+    progress: Option<&Progress>,
+) -> io::Result<()> {
+    let time = sample
+        .time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0);
+    writeln!(
+        out,
+        "{{\"time\":{:.3},\"value\":{},\"max\":{},\"rate\":{},\"eta_secs\":{}}}",
+        time,
+        json_number(Some(sample.value)),
+        json_number(sample.max),
+        json_number(progress.map(|progress| progress.rate)),
+        json_number(progress.and_then(|progress| progress.eta)),
+    )
+}
+
+/// Format a number as JSON. JSON can't represent non-finite numbers, so they
+/// are written as `null`.
+fn json_number(x: Option<f64>) -> String {
+    match x.filter(|x| x.is_finite()) {
+        Some(x) => x.to_string(),
+        None => "null".to_owned(),
+    }
+}
+
+/// Write the current progress as CSV comment lines.
+pub fn write_progress_comment(out: &mut impl Write, progress: &Progress) -> io::Result<()> {
+    writeln!(out, "# value: {}", progress.value)?;
+    if let Some(max) = progress.max {
+        writeln!(out, "# max: {}", max)?;
+    }
+    writeln!(out, "# rate: {}", progress.rate)?;
+    if let Some(eta) = progress.eta {
+        writeln!(out, "# eta: {}", eta)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Runs the user's commands when the progress reaches certain points.
+//!
+//! The commands are run by `sh -c` with the following environment variables:
+//! `PVFILT_EVENT` (`complete` or `threshold`), `PVFILT_VALUE`, and, if known,
+//! `PVFILT_MAX`, `PVFILT_RATE`, `PVFILT_ETA` (seconds), and
+//! `PVFILT_THRESHOLD`.
+use std::{
+    io,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use crate::analysis::Analyzer;
+
+/// A command run when the value crosses a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub value: f64,
+    pub cmd: String,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    /// Parse a threshold in the `VALUE:CMD` format, e.g., `500:echo halfway`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid threshold (expected `VALUE:CMD`): {}", s);
+        let i = s.find(':').ok_or_else(invalid)?;
+        let value = s[..i].trim().parse().map_err(|_| invalid())?;
+        let cmd = s[i + 1..].to_owned();
+        if cmd.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { value, cmd })
+    }
+}
+
+/// Tracks the progress to determine when to run the hooks.
+pub struct Hooks {
+    on_complete: Option<String>,
+    thresholds: Vec<Threshold>,
+    /// The value when `update` was last called.
+    last_value: Option<f64>,
+    /// `on_complete` has been run.
+    completed: bool,
+}
+
+impl Hooks {
+    pub fn new(on_complete: Option<String>, thresholds: Vec<Threshold>) -> Self {
+        Self {
+            on_complete,
+            thresholds,
+            last_value: None,
+            completed: false,
+        }
+    }
+
+    /// Run the hooks triggered since the last call. The run is complete when
+    /// the value reaches the maximum value or `succeeded` is set. Returns the
+    /// errors that occurred while spawning the commands.
+    pub fn update(&mut self, analyzer: &Analyzer, succeeded: bool) -> Vec<io::Error> {
+        let samples = analyzer.filtered_samples();
+        let last = match samples.back() {
+            Some(last) => last,
+            None => return Vec::new(),
+        };
+        let progress = analyzer.progress();
+
+        let mut env = vec![("PVFILT_VALUE", last.value.to_string())];
+        env.extend(last.max.map(|max| ("PVFILT_MAX", max.to_string())));
+        if let Some(progress) = progress.filter(|p| p.rate.is_finite()) {
+            env.push(("PVFILT_RATE", progress.rate.to_string()));
+            env.extend(progress.eta.map(|eta| ("PVFILT_ETA", eta.to_string())));
+        }
+
+        let mut errors = Vec::new();
+
+        if let Some(last_value) = self.last_value {
+            for threshold in &self.thresholds {
+                // Crossed in either direction
+                if (last_value < threshold.value) != (last.value < threshold.value) {
+                    let mut env = env.clone();
+                    env.push(("PVFILT_EVENT", "threshold".to_owned()));
+                    env.push(("PVFILT_THRESHOLD", threshold.value.to_string()));
+                    errors.extend(spawn(&threshold.cmd, &env).err());
+                }
+            }
+        }
+        self.last_value = Some(last.value);
+
+        let reached_max = last.max.map_or(false, |max| last.value >= max);
+        if let (Some(cmd), false, true) =
+            (&self.on_complete, self.completed, reached_max || succeeded)
+        {
+            self.completed = true;
+            env.push(("PVFILT_EVENT", "complete".to_owned()));
+            errors.extend(spawn(cmd, &env).err());
+        }
+
+        errors
+    }
+}
+
+/// Run `cmd` in the background. Its output is discarded so as not to disturb
+/// the user interface.
+fn spawn(cmd: &str, env: &[(&str, String)]) -> Result<(), io::Error> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Reap the process when it exits
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_threshold() {
+        assert_eq!(
+            "500:echo a:b".parse(),
+            Ok(Threshold {
+                value: 500.0,
+                cmd: "echo a:b".to_owned()
+            })
+        );
+        assert!("500".parse::<Threshold>().is_err());
+        assert!("half:echo".parse::<Threshold>().is_err());
+        assert!("500: ".parse::<Threshold>().is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! The key bindings. The help window is generated from `BINDINGS`, so it
+//! always reflects the keys actually handled.
+use termion::event::Key;
+
+/// An operation triggered by a key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    ToggleHelp,
+    ScrollUp,
+    ScrollDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    FocusNextPane,
+    SaveSnapshot,
+    ExportCsv,
+    CopyStatus,
+    ToggleHistogram,
+    CycleChartMode,
+    ToggleLogScale,
+    CycleSlowestRuns,
+    Rerun,
+    TogglePause,
+    ToggleStderr,
+    CycleOutputSplit,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    PanLeft,
+    PanRight,
+    ScrollToTop,
+    ScrollToBottom,
+}
+
+pub struct Binding {
+    pub keys: &'static [Key],
+    /// How `keys` are displayed in the help window.
+    pub label: &'static str,
+    pub description: &'static str,
+    pub action: Action,
+}
+
+/// The key bindings in the order they are listed in the help window. The quit
+/// keys are configurable and thus handled separately.
+pub const BINDINGS: &[Binding] = &[
+    Binding {
+        keys: &[Key::Char('h')],
+        label: "h",
+        description: "Toggle this help window",
+        action: Action::ToggleHelp,
+    },
+    Binding {
+        keys: &[Key::Up],
+        label: "UP",
+        description: "Scroll this help window or the output up",
+        action: Action::ScrollUp,
+    },
+    Binding {
+        keys: &[Key::Down],
+        label: "DOWN",
+        description: "Scroll this help window or the output down",
+        action: Action::ScrollDown,
+    },
+    Binding {
+        keys: &[Key::PageUp],
+        label: "PGUP",
+        description: "Scroll the output up by a page",
+        action: Action::ScrollPageUp,
+    },
+    Binding {
+        keys: &[Key::PageDown],
+        label: "PGDN",
+        description: "Scroll the output down by a page",
+        action: Action::ScrollPageDown,
+    },
+    Binding {
+        keys: &[Key::Char('s')],
+        label: "s",
+        description: "Save a snapshot",
+        action: Action::SaveSnapshot,
+    },
+    Binding {
+        keys: &[Key::Char('x')],
+        label: "x",
+        description: "Export the samples to the --export-csv file",
+        action: Action::ExportCsv,
+    },
+    Binding {
+        keys: &[Key::Char('y')],
+        label: "y",
+        description: "Copy the status to the clipboard",
+        action: Action::CopyStatus,
+    },
+    Binding {
+        keys: &[Key::Char('v')],
+        label: "v",
+        description: "Toggle the rate histogram",
+        action: Action::ToggleHistogram,
+    },
+    Binding {
+        keys: &[Key::Char('r')],
+        label: "r",
+        description: "Cycle the value/rate/both charts",
+        action: Action::CycleChartMode,
+    },
+    Binding {
+        keys: &[Key::Char('L')],
+        label: "L",
+        description: "Toggle the logarithmic y axes",
+        action: Action::ToggleLogScale,
+    },
+    Binding {
+        keys: &[Key::Char('o')],
+        label: "o",
+        description: "Cycle through the slowest runs",
+        action: Action::CycleSlowestRuns,
+    },
+    Binding {
+        keys: &[Key::Char(' ')],
+        label: "SPACE",
+        description: "Run the command now",
+        action: Action::Rerun,
+    },
+    Binding {
+        keys: &[Key::Char('p')],
+        label: "p",
+        description: "Pause/resume sampling",
+        action: Action::TogglePause,
+    },
+    Binding {
+        keys: &[Key::Char('e')],
+        label: "e",
+        description: "Show/hide the stderr pane",
+        action: Action::ToggleStderr,
+    },
+    Binding {
+        keys: &[Key::Char('\t')],
+        label: "TAB",
+        description: "Switch between the stdout and stderr panes",
+        action: Action::FocusNextPane,
+    },
+    Binding {
+        keys: &[Key::Char('|')],
+        label: "|",
+        description: "Change the stdout/stderr split",
+        action: Action::CycleOutputSplit,
+    },
+    Binding {
+        keys: &[Key::Char(']'), Key::Char('+')],
+        label: "] +",
+        description: "Zoom in the time axis",
+        action: Action::ZoomIn,
+    },
+    Binding {
+        keys: &[Key::Char('['), Key::Char('-')],
+        label: "[ -",
+        description: "Zoom out the time axis",
+        action: Action::ZoomOut,
+    },
+    Binding {
+        keys: &[Key::Char('0')],
+        label: "0",
+        description: "Reset the zoom and the pan",
+        action: Action::ResetZoom,
+    },
+    Binding {
+        keys: &[Key::Left],
+        label: "LEFT",
+        description: "Pan the time axis to the past",
+        action: Action::PanLeft,
+    },
+    Binding {
+        keys: &[Key::Right],
+        label: "RIGHT",
+        description: "Pan the time axis to the present",
+        action: Action::PanRight,
+    },
+    Binding {
+        keys: &[Key::Char('g'), Key::Home],
+        label: "g HOME",
+        description: "Jump to the top of the output",
+        action: Action::ScrollToTop,
+    },
+    Binding {
+        keys: &[Key::Char('G'), Key::End],
+        label: "G END",
+        description: "Jump to the bottom of the output",
+        action: Action::ScrollToBottom,
+    },
+];
+
+/// Find the action bound to `key`.
+pub fn action(key: Key) -> Option<Action> {
+    BINDINGS
+        .iter()
+        .find(|binding| binding.keys.contains(&key))
+        .map(|binding| binding.action)
+}
@@ -0,0 +1,78 @@
+//! Extracts numbers from a program's output and estimates the progress.
+//!
+//! The `pvfilt` binary is a user interface over this library. Other tools can
+//! embed the sample extraction and the ETA estimation through `Session`:
+//!
+//! ```no_run
+//! use pvfilt::{analysis, runner, Session};
+//!
+//! let mut session = Session::new(analysis::Options::default());
+//! session.run_once(vec!["dmsetup".into(), "status".into()], &runner::Options::default());
+//! if let Some(progress) = session.progress() {
+//!     println!("{} ETA {:?}", progress.value, progress.eta);
+//! }
+//! ```
+use std::{collections::VecDeque, ffi::OsString};
+
+pub mod analysis;
+pub mod ansi;
+pub mod export;
+pub mod runner;
+pub mod session;
+pub mod unit;
+
+use analysis::{Analyzer, Progress, Sample};
+use runner::{CmdResult, RunError};
+
+/// Feeds a command's outputs to an `Analyzer`.
+pub struct Session {
+    analyzer: Analyzer,
+    /// The error of the latest run, if it failed.
+    last_error: Option<RunError>,
+}
+
+impl Session {
+    pub fn new(options: analysis::Options) -> Self {
+        Self {
+            analyzer: Analyzer::new(options),
+            last_error: None,
+        }
+    }
+
+    /// Run `cmd` to completion, processing its output as it arrives.
+    pub fn run_once(&mut self, cmd: Vec<OsString>, options: &runner::Options) {
+        runner::run_once(cmd, options, |result| self.process_result(result));
+    }
+
+    /// Process an output of the command. This can be used as the callback of
+    /// the functions in `runner`, e.g., `runner::watch_cmd`.
+    pub fn process_result(&mut self, result: CmdResult) {
+        match result {
+            Ok(output) => {
+                self.analyzer.process_output(&output);
+                self.last_error = None;
+            }
+            Err(e) => self.last_error = Some(e),
+        }
+    }
+
+    /// Get the samples extracted so far, oldest first.
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.analyzer.samples
+    }
+
+    /// Get the current progress. Returns `None` if there are not enough
+    /// samples yet.
+    pub fn progress(&self) -> Option<Progress> {
+        self.analyzer.progress()
+    }
+
+    /// Get the error of the latest run, if it failed.
+    pub fn last_error(&self) -> Option<&RunError> {
+        self.last_error.as_ref()
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+}
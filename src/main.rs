@@ -1,9 +1,12 @@
 use std::{
     ffi::OsString,
-    io,
-    sync::{mpsc, Mutex},
+    fs,
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
-use structopt::StructOpt;
+use structopt::{clap::AppSettings, StructOpt};
 use termion::{
     event::{Event, Key},
     input::TermRead,
@@ -11,71 +14,771 @@ use termion::{
 };
 use tui::{backend::TermionBackend, Terminal};
 
-mod analysis;
+use pvfilt::{analysis, ansi, export, runner, session, unit};
+
+mod clipboard;
+mod config;
+mod desktop;
 mod draw;
-mod runner;
+mod hooks;
+mod keys;
+mod svg;
+mod theme;
 
-#[derive(StructOpt)]
+#[derive(StructOpt, Clone)]
 #[structopt(
     name = "pvfilt",
-    about = "Process a program's output to generate charts, etc."
+    about = "Process a program's output to generate charts, etc.",
+    // Options from the configuration file are overridden by later ones
+    setting = AppSettings::AllArgsOverrideSelf
 )]
 struct Opt {
-    /// The command to execute. stdin will be used if omitted.
+    /// The command to execute. stdin will be used if omitted, in which case
+    /// the lines are processed as they are read (see `--aggregate latest`).
     #[structopt(last = true, parse(from_os_str))]
     cmd: Vec<OsString>,
 
+    /// Apply the options of the named profile in the configuration file.
+    #[structopt(long = "profile", value_name = "NAME")]
+    profile: Option<String>,
+
     /// Execute the command periodically like watch(1). Ignored if the command
     /// is not given.
-    #[structopt(short = "w")]
+    #[structopt(short = "w", long = "watch")]
     watch: bool,
+
+    /// The interval between runs in watch mode, in seconds (e.g., `0.5`) or
+    /// as a duration (e.g., `500ms`, `2m`).
+    #[structopt(
+        short = "n",
+        long = "interval",
+        default_value = "1",
+        parse(try_from_str = runner::parse_interval)
+    )]
+    interval: Duration,
+
+    /// Execute the command only once and follow it until it exits. The
+    /// chart spans from the launch to the exit, and the display is frozen
+    /// after the exit. This is the default unless `-w` is given.
+    #[structopt(long = "until-exit", conflicts_with = "watch")]
+    until_exit: bool,
+
+    /// The comma-separated exit codes that indicate success.
+    #[structopt(
+        long = "success-codes",
+        value_name = "CODES",
+        default_value = "0",
+        use_delimiter = true
+    )]
+    success_codes: Vec<i32>,
+
+    /// Only consider a run successful if its stdout or stderr matches this
+    /// regular expression, in addition to `--success-codes`.
+    #[structopt(long = "success-pattern", value_name = "REGEX")]
+    success_pattern: Option<regex::Regex>,
+
+    /// Read the given file periodically instead of executing a command. The
+    /// entire file is examined every time. The path is reopened for every
+    /// read, so a file that is rotated or truncated is followed seamlessly.
+    #[structopt(
+        long = "watch-file",
+        parse(from_os_str),
+        conflicts_with_all = &["cmd", "until-exit"]
+    )]
+    watch_file: Option<PathBuf>,
+
+    /// The working directory of the command.
+    #[structopt(
+        long = "cwd",
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with = "watch-file"
+    )]
+    cwd: Option<PathBuf>,
+
+    /// Set an environment variable for the command, e.g., `LC_ALL=C`. Can be
+    /// specified multiple times.
+    #[structopt(
+        long = "env",
+        value_name = "KEY=VAL",
+        number_of_values = 1,
+        parse(try_from_str = runner::parse_env_var)
+    )]
+    env: Vec<(String, String)>,
+
+    /// Watch another shell command in a separate chart, e.g.,
+    /// `--cmd 'du -s build'`. Can be specified multiple times. The first one
+    /// becomes the main command if none is given after `--`. The other ones
+    /// are ignored with `--no-ui`.
+    #[structopt(long = "cmd", value_name = "CMD", number_of_values = 1)]
+    extra_cmds: Vec<String>,
+
+    /// Run the command through the shell (`$SHELL -c`). The arguments are
+    /// joined by spaces and passed verbatim.
+    #[structopt(short = "c", long = "shell")]
+    shell: bool,
+
+    /// The unit of the values. `bytes` and `ibytes` display values with
+    /// decimal (kB, MB, ...) and binary (KiB, MiB, ...) prefixes,
+    /// respectively. With `ibytes`, captured numbers with suffixes like `G`
+    /// are also read as binary multiples. Any other string is appended to
+    /// values as it is.
+    #[structopt(long = "unit", default_value = "none")]
+    unit: unit::Unit,
+
+    /// Don't fall back to the first number in the output when the pattern
+    /// doesn't match.
+    #[structopt(long = "no-autodetect")]
+    no_autodetect: bool,
+
+    /// Ignore values that jump by orders of magnitude from the recent trend,
+    /// e.g., numbers caught from unrelated output. A value is accepted after
+    /// a few consecutive rejections.
+    #[structopt(long = "reject-outliers")]
+    reject_outliers: bool,
+
+    /// How the maximum value is determined. `captured` uses the one captured
+    /// along with each value. `seen` uses the largest value or maximum value
+    /// seen so far. `first` uses the first one captured. `fixed` uses the
+    /// value given by `--max`.
+    #[structopt(
+        long = "max-mode",
+        default_value = "captured",
+        possible_values = &["captured", "seen", "first", "fixed"]
+    )]
+    max_mode: String,
+
+    /// How the command's output is decoded. `raw-hex` decodes it as
+    /// `utf8-lossy` but displays a hex dump.
+    #[structopt(
+        long = "encoding",
+        default_value = "utf8-lossy",
+        possible_values = runner::Encoding::NAMES
+    )]
+    encoding: runner::Encoding,
+
+    /// How captured values are interpreted. `number` accepts thousands
+    /// separators and SI or IEC suffixes, e.g., `3,214,000`, `1.5G`, and
+    /// `12MiB`. `duration` accepts the `[HH:]MM:SS[.fff]` format.
+    #[structopt(
+        long = "parse",
+        default_value = "number",
+        possible_values = analysis::ParseMode::NAMES
+    )]
+    parse_mode: analysis::ParseMode,
+
+    /// Treat the captured values as the remaining amounts, e.g., for a
+    /// command counting down. The maximum value minus them is recorded, so
+    /// the maximum value must be known. With `--max-mode seen`, the first
+    /// (largest) remaining amount is taken as the maximum value.
+    #[structopt(long = "invert", conflicts_with = "meter")]
+    invert: bool,
+
+    /// Chart the amount of output instead of the values found in it, making
+    /// pvfilt a throughput monitor for any command. `bytes` or `lines` of
+    /// stdout are counted.
+    #[structopt(long = "meter", possible_values = analysis::Meter::NAMES)]
+    meter: Option<analysis::Meter>,
+
+    /// Add up the amount of output of all runs with `--meter`. By default,
+    /// each run is measured separately.
+    #[structopt(long = "cumulative", requires = "meter")]
+    cumulative: bool,
+
+    /// Parse each line of the output (or, failing that, the whole output) as
+    /// JSON and extract the value referenced by this JSON pointer, e.g.,
+    /// `/progress/done`. Numbers in strings are accepted.
+    #[structopt(
+        long = "json",
+        value_name = "POINTER",
+        parse(try_from_str = analysis::json::parse_pointer),
+        conflicts_with_all = &["regex", "meter"]
+    )]
+    json: Option<String>,
+
+    /// Extract values from logfmt-style `key=value` pairs, e.g., given
+    /// `done=value total=max`, `done=1523 total=9000` yields 1523 out of
+    /// 9000.
+    #[structopt(
+        long = "kv",
+        value_name = "KEY=value [KEY=max]",
+        conflicts_with_all = &["regex", "meter", "json"]
+    )]
+    kv: Option<analysis::logfmt::Keys>,
+
+    /// The JSON pointer of the maximum value with `--json`.
+    #[structopt(
+        long = "json-max",
+        value_name = "POINTER",
+        parse(try_from_str = analysis::json::parse_pointer),
+        requires = "json"
+    )]
+    json_max: Option<String>,
+
+    /// Extract percentages like `42%` and `42.7 %` as values out of 100
+    /// instead of matching the default `N/M` pattern.
+    #[structopt(
+        long = "percent",
+        conflicts_with_all = &["regex", "meter", "json", "kv", "max"]
+    )]
+    percent: bool,
+
+    /// Extract values using this regular expression instead of the default
+    /// `N/M` pattern. The first capture group captures the value, and the
+    /// second one, if any, captures the maximum value. Named groups after them
+    /// are charted as separate series, e.g., `(\d+)/(\d+), (?P<errors>\d+)`.
+    #[structopt(
+        long = "regex",
+        value_name = "REGEX",
+        parse(try_from_str = analysis::parse_pattern)
+    )]
+    regex: Option<regex::Regex>,
+
+    /// Only scan the lines matching this regular expression for values.
+    #[structopt(long = "match")]
+    line_match: Option<regex::Regex>,
+
+    /// Don't scan the lines matching this regular expression for values.
+    #[structopt(long = "ignore")]
+    line_ignore: Option<regex::Regex>,
+
+    /// Mark the time when a line matching this regular expression appears
+    /// on the chart, labeled with the first capture group or the line.
+    #[structopt(long = "annotate", value_name = "REGEX")]
+    annotate: Option<regex::Regex>,
+
+    /// The number of bytes retained from each of stdout and stderr per run.
+    /// Once exceeded, the oldest lines are discarded (but still scanned for
+    /// values), and so is the part of a line beyond this length.
+    #[structopt(long = "max-output-bytes", default_value = "262144")]
+    max_output_bytes: usize,
+
+    /// Display ANSI escape sequences in the output as they are instead of
+    /// interpreting colors. By default, they are also removed before matching.
+    #[structopt(long = "no-ansi")]
+    no_ansi: bool,
+
+    /// Display stdout and stderr merged in a single pane in the order they
+    /// were written, each line prefixed with the time since the start of the
+    /// run.
+    #[structopt(long = "interleave", conflicts_with = "watch-file")]
+    interleave: bool,
+
+    /// Retain the output of the N longest-running runs, which can be viewed by
+    /// pressing `o`.
+    #[structopt(long = "keep-slowest", value_name = "N", default_value = "0")]
+    keep_slowest: usize,
+
+    /// The time span shown in the chart, e.g., `2m`. Defaults to `--window`
+    /// if given, or the entire history.
+    #[structopt(long = "span", parse(try_from_str = humantime::parse_duration))]
+    span: Option<Duration>,
+
+    /// What the time series chart plots: `value`, `rate`, or `both` (stacked).
+    /// The `r` key cycles through these.
+    #[structopt(
+        long = "chart",
+        default_value = "rate",
+        possible_values = draw::ChartMode::NAMES
+    )]
+    chart: draw::ChartMode,
+
+    /// Use a logarithmic scale for the y axes of the time series charts.
+    /// Non-positive values are not plotted. The `L` key toggles this.
+    #[structopt(long = "log-scale")]
+    log_scale: bool,
+
+    /// Stack the value and the extra series in the value chart so that the
+    /// topmost line shows their total, e.g., `--chart value --regex
+    /// 'cpu=(?P<cpu>\d+)(?:/(\d+))? io=(?P<io>\d+) idle=(?P<idle>\d+)'`. A
+    /// series missing from a sample keeps its last value.
+    #[structopt(long = "stack")]
+    stack: bool,
+
+    /// Only retain the samples from this long before the latest one, e.g.,
+    /// `10m`. The rate, the ETA, and the chart are based on this rolling
+    /// window instead of the entire history.
+    #[structopt(long = "window", parse(try_from_str = humantime::parse_duration))]
+    window: Option<Duration>,
+
+    /// How multiple matches in an output are combined into a sample: `latest`
+    /// (the last match in the lines output since the last sample), `first`,
+    /// `last`, `nth:K` (the K-th match), `sum`, `max`, `mean`, or `all`
+    /// (record every match as a separate sample). With `latest` and `all`,
+    /// new lines are processed as they are output. Otherwise, the entire
+    /// output is processed after the command exits.
+    #[structopt(long = "aggregate", default_value = "latest", value_name = "MODE")]
+    aggregate: analysis::Aggregate,
+
+    /// Which match in an output is sampled: `first`, `last`, `nth:K` (the
+    /// K-th match), or `all` (every match). This is a shorthand for the
+    /// corresponding `--aggregate` modes.
+    #[structopt(
+        long = "match-line",
+        value_name = "MATCH",
+        conflicts_with = "aggregate",
+        parse(try_from_str = analysis::parse_match_selection)
+    )]
+    match_line: Option<analysis::Aggregate>,
+
+    /// Show how the recent outputs were processed, e.g., what the pattern
+    /// captured.
+    #[structopt(long = "debug")]
+    debug: bool,
+
+    /// The target rate (per second). A reference line is drawn at this value,
+    /// and the rate below it is highlighted.
+    #[structopt(long = "rate-target")]
+    rate_target: Option<f64>,
+
+    /// The relative widths of the stdout and stderr panes in the
+    /// `STDOUT:STDERR` format, e.g., `1:3`. Each part can be up to 100. A pane
+    /// collapses regardless of this if the corresponding stream is empty.
+    #[structopt(long = "output-split", default_value = "1:1")]
+    output_split: draw::OutputSplit,
+
+    /// The color theme. Defaults to `mono` if the `NO_COLOR` environment
+    /// variable is set, `default` otherwise.
+    #[structopt(long = "theme", possible_values = theme::Theme::NAMES)]
+    theme: Option<theme::Theme>,
+
+    /// Run this shell command when the value reaches the maximum value or,
+    /// with `--until-exit`, the command succeeds. The progress is passed via
+    /// the environment variables `PVFILT_VALUE`, `PVFILT_MAX`, `PVFILT_RATE`,
+    /// and `PVFILT_ETA`.
+    #[structopt(long = "on-complete", value_name = "CMD")]
+    on_complete: Option<String>,
+
+    /// Run a shell command when the value crosses the given value, e.g.,
+    /// `500:echo halfway`. `PVFILT_THRESHOLD` is set in addition to the
+    /// variables of `--on-complete`. Can be specified multiple times.
+    #[structopt(long = "on-threshold", value_name = "VALUE:CMD", number_of_values = 1)]
+    on_threshold: Vec<hooks::Threshold>,
+
+    /// Show a desktop notification when the value reaches the maximum value
+    /// or, with `--until-exit`, the command succeeds. Uses `notify-send` or
+    /// `osascript`.
+    #[structopt(long = "notify")]
+    notify: bool,
+
+    /// Don't display the progress and rate gauges.
+    #[structopt(long = "no-gauge")]
+    no_gauge: bool,
+
+    /// The foreground color of the progress gauge. Either a color name (e.g.,
+    /// `lightred`) or a 256-color palette index. Overrides the theme.
+    #[structopt(long = "gauge-color", parse(try_from_str = draw::parse_color))]
+    gauge_color: Option<tui::style::Color>,
+
+    /// Color the progress gauge with the warning color while the ETA is at
+    /// least this long, e.g., `10m`.
+    #[structopt(long = "eta-warn", parse(try_from_str = humantime::parse_duration))]
+    eta_warn: Option<Duration>,
+
+    /// Color the progress gauge with the error color while the ETA is at
+    /// least this long.
+    #[structopt(long = "eta-crit", parse(try_from_str = humantime::parse_duration))]
+    eta_crit: Option<Duration>,
+
+    /// The background color of the progress gauge. Overrides the theme.
+    #[structopt(long = "gauge-background", parse(try_from_str = draw::parse_color))]
+    gauge_background: Option<tui::style::Color>,
+
+    /// Smooth the values with a rolling median filter of the given window
+    /// size before charting and calculating the rate. Exported data is not
+    /// affected.
+    #[structopt(long = "median")]
+    median: Option<usize>,
+
+    /// Smooth the rate with a simple moving average of N rates (`sma:N`) or
+    /// an exponentially weighted moving average with the smoothing factor
+    /// ALPHA (`ewma:ALPHA`), e.g., `ewma:0.3`. The chart, the current rate, and
+    /// the ETA are based on the smoothed rate.
+    #[structopt(long = "smooth", value_name = "sma:N or ewma:ALPHA")]
+    smooth: Option<analysis::Smoothing>,
+
+    /// Estimate the ETA from the samples in this recent period, e.g., `5m`,
+    /// instead of all of them (excluding the warmup period). A line is fitted
+    /// to the samples, and the spread around it determines the range shown
+    /// below the ETA. Not used with `--smooth`.
+    #[structopt(long = "eta-window", parse(try_from_str = humantime::parse_duration))]
+    eta_window: Option<Duration>,
+
+    /// Alert in the status pane if the value doesn't change for this long,
+    /// e.g., `2m`.
+    #[structopt(long = "stall-timeout", parse(try_from_str = humantime::parse_duration))]
+    stall_timeout: Option<Duration>,
+
+    /// Display rates in scientific notation instead of choosing a time scale
+    /// and an SI prefix automatically.
+    #[structopt(long = "sci")]
+    sci: bool,
+
+    /// Display the value projected at the current average rate after the
+    /// specified time, e.g., `5m`.
+    #[structopt(long = "project", parse(try_from_str = humantime::parse_duration))]
+    project: Option<Duration>,
+
+    /// Show the line the latest value was found in at the bottom of the
+    /// status pane.
+    #[structopt(long = "show-match")]
+    show_match: bool,
+
+    /// Draw gridlines at the intermediate ticks of the chart.
+    #[structopt(long = "grid")]
+    grid: bool,
+
+    /// The number of decimal places in displayed values and rates. By
+    /// default, it's chosen from the magnitude of each number.
+    #[structopt(long = "precision", value_name = "N")]
+    precision: Option<usize>,
+
+    /// How a decrease in the value, e.g., a counter restarting on every run,
+    /// is treated. `segment` starts a new segment, breaking the rate chart.
+    /// `cumulative` adds up the runs to make the values monotonic.
+    #[structopt(
+        long = "reset-mode",
+        default_value = "none",
+        possible_values = analysis::ResetMode::NAMES
+    )]
+    reset_mode: analysis::ResetMode,
+
+    /// The number of bins in the rate histogram.
+    #[structopt(long = "histogram-bins", default_value = "10")]
+    histogram_bins: usize,
+
+    /// The delay before running the command for the first time, e.g., `5s`.
+    #[structopt(
+        long = "initial-delay",
+        default_value = "0s",
+        parse(try_from_str = humantime::parse_duration)
+    )]
+    initial_delay: Duration,
+
+    /// Don't record a sample with the same value as the last one until this
+    /// much time has passed since it, e.g., `500ms`. The value at the end of
+    /// a run is always recorded.
+    #[structopt(
+        long = "min-sample-interval",
+        default_value = "0s",
+        parse(try_from_str = humantime::parse_duration)
+    )]
+    min_sample_interval: Duration,
+
+    /// Exclude the initial period from the rate and the ETA, either as a
+    /// duration (e.g., `30s`) or a number of samples. The rate during the
+    /// period is still charted, but dimmed.
+    #[structopt(long = "warmup", value_name = "DURATION or N")]
+    warmup: Option<analysis::Warmup>,
+
+    /// Quit automatically after the given duration, e.g., `30m`.
+    #[structopt(long = "max-duration", parse(try_from_str = humantime::parse_duration))]
+    max_duration: Option<Duration>,
+
+    /// Execute the command only once without the user interface, and then
+    /// write a chart of the values and the rate to the specified SVG file.
+    #[structopt(
+        long = "render-svg",
+        value_name = "PATH",
+        parse(from_os_str),
+        conflicts_with_all = &["watch", "until-exit", "watch-file"]
+    )]
+    render_svg: Option<PathBuf>,
+
+    /// Don't display the user interface. Instead, print each new sample to
+    /// stdout as a JSON object with the fields `time` (UNIX time), `value`,
+    /// `max`, `rate`, and `eta_secs`, one per line. Exits when the command
+    /// exits or stdin ends.
+    #[structopt(long = "no-ui")]
+    no_ui: bool,
+
+    /// Print the samples to stdout on exit as whitespace-separated
+    /// `elapsed value max` rows.
+    #[structopt(long = "dump")]
+    dump: bool,
+
+    /// Write the current progress to the specified file whenever it's updated
+    /// as a JSON object with the fields `value`, `max`, `pct`, `rate`,
+    /// `eta_secs`, and `elapsed_secs`. A regular file is overwritten each time,
+    /// while other files (e.g., `/dev/fd/3`) receive one object per line.
+    #[structopt(long = "print-eta-json", value_name = "PATH")]
+    print_eta_json: Option<PathBuf>,
+
+    /// Write the command's output to the specified file as it is, i.e.,
+    /// without decoding or truncation. In watch mode, each run is preceded by
+    /// a `==> <time> <==` line.
+    #[structopt(long = "tee", value_name = "PATH")]
+    tee: Option<PathBuf>,
+
+    /// The key that quits the application.
+    #[structopt(long = "quit-key", default_value = "q")]
+    quit_key: char,
+
+    /// Don't quit the application when ESC is pressed.
+    #[structopt(long = "no-esc-quit")]
+    no_esc_quit: bool,
+
+    /// Don't quit the application when Ctrl-C is pressed.
+    #[structopt(long = "no-ctrl-c-quit")]
+    no_ctrl_c_quit: bool,
+
+    /// Save the samples to the given file on exit.
+    #[structopt(long = "save-session", parse(from_os_str))]
+    save_session: Option<PathBuf>,
+
+    /// Write the samples as CSV to the given file on exit. The `x` key writes
+    /// it on demand.
+    #[structopt(long = "export-csv", value_name = "PATH", parse(from_os_str))]
+    export_csv: Option<PathBuf>,
+
+    /// Load the samples saved by `--save-session` from the given file.
+    #[structopt(long = "load-session", parse(from_os_str))]
+    load_session: Option<PathBuf>,
+
+    /// The maximum value for `--max-mode fixed`. Implies `--max-mode fixed`.
+    #[structopt(long = "max", required_if("max-mode", "fixed"))]
+    max: Option<f64>,
+}
+
+impl Opt {
+    /// Check if the input is read from stdin, i.e., neither a command nor
+    /// `--watch-file` is given.
+    fn reads_stdin(&self) -> bool {
+        self.cmd.is_empty() && self.watch_file.is_none()
+    }
 }
 
 fn main() -> Result<(), io::Error> {
-    let mut opt = Opt::from_args();
+    let mut opt = parse_opt();
 
-    if opt.cmd.is_empty() {
-        panic!("not implemented: stdin mode");
+    if opt.parse_mode == analysis::ParseMode::Duration && opt.unit == unit::Unit::None {
+        opt.unit = unit::Unit::Duration;
+    }
+    if opt.meter == Some(analysis::Meter::Bytes) && opt.unit == unit::Unit::None {
+        opt.unit = unit::Unit::Bytes;
+    }
+    if let Some(match_line) = opt.match_line {
+        opt.aggregate = match_line;
     }
-    if !opt.watch {
-        panic!("not implemented: !watch");
+    if opt.percent {
+        opt.regex = Some(analysis::parse_pattern(analysis::PERCENT_PATTERN).unwrap());
+        opt.max = Some(100.0);
     }
 
-    let (event_recv, event_send) = start_event_loop()?;
+    if opt.reads_stdin() && (opt.interleave || opt.render_svg.is_some()) {
+        eprintln!("error: `--interleave` and `--render-svg` require a command");
+        std::process::exit(1);
+    }
+    if let Some(path) = opt.render_svg.clone() {
+        return render_svg(&opt, &path);
+    }
+    if !opt.watch && opt.watch_file.is_none() && !opt.reads_stdin() {
+        // Follow a single run by default
+        opt.until_exit = true;
+    }
 
-    let stdout = io::stdout().into_raw_mode()?;
-    let stdout = termion::screen::AlternateScreen::from(stdout);
-    let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.hide_cursor()?;
+    if let Some(cwd) = &opt.cwd {
+        if !cwd.is_dir() {
+            eprintln!("error: {} is not a directory", cwd.display());
+            std::process::exit(1);
+        }
+    }
 
-    watch_resize(event_send.clone())?;
+    let loaded_session = opt.load_session.as_ref().map(|path| {
+        session::load(path).unwrap_or_else(|e| {
+            eprintln!("error: failed to load the session: {}", e);
+            std::process::exit(1);
+        })
+    });
 
-    let cmd_string = cmd_to_string(&opt.cmd);
+    let create_file = |path: &PathBuf| {
+        fs::File::create(path).unwrap_or_else(|e| {
+            eprintln!("error: failed to open {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    };
+    let eta_json = opt.print_eta_json.as_ref().map(create_file);
 
-    let worker = start_worker(&mut opt, event_send);
+    if opt
+        .tee
+        .as_ref()
+        .map_or(false, |path| path.as_os_str() == "-")
+    {
+        eprintln!("error: `--tee -` is not supported because the user interface occupies stdout");
+        std::process::exit(1);
+    }
+    let tee = opt.tee.as_ref().map(create_file).map(Arc::new);
 
-    let mut app = AppState {
-        worker,
-        show_help: false,
-        cmd_string,
+    let (event_recv, event_send) = if opt.no_ui {
+        // There's no terminal to read keys from
+        let (send, recv) = mpsc::channel();
+        (recv, AppEventSender(send))
+    } else {
+        start_event_loop()?
     };
 
-    app.draw(&mut terminal)?;
+    if let Some(duration) = opt.max_duration {
+        quit_after(duration, event_send.clone());
+    }
 
-    for e in event_recv.iter() {
-        if app.process_event(e?, &mut terminal)? {
-            break;
+    let cmd_string = if let Some(path) = &opt.watch_file {
+        path.display().to_string()
+    } else if opt.reads_stdin() {
+        "stdin".to_owned()
+    } else if opt.shell {
+        runner::join_args(&opt.cmd).to_string_lossy().into_owned()
+    } else {
+        cmd_to_string(&opt.cmd)
+    };
+
+    let worker = start_worker(&opt, eta_json, tee, event_send.clone());
+
+    // The other `--cmd`s are only charted, which `--no-ui` doesn't do
+    let extra_workers = if opt.no_ui {
+        Vec::new()
+    } else {
+        opt.extra_cmds
+            .iter()
+            .map(|cmd| {
+                let mut cfg = opt.clone();
+                cfg.cmd = vec![cmd.into()];
+                cfg.shell = true;
+                cfg.watch_file = None;
+                cfg.on_complete = None;
+                cfg.on_threshold.clear();
+                (
+                    cmd.clone(),
+                    start_worker(&cfg, None, None, event_send.clone()),
+                )
+            })
+            .collect()
+    };
+
+    if let Some(mut loaded_session) = loaded_session {
+        let mismatches: Vec<_> = [
+            ("command", &loaded_session.command, cmd_string.as_str()),
+            (
+                "parse mode",
+                &loaded_session.parse_mode,
+                opt.parse_mode.name(),
+            ),
+            (
+                "aggregation",
+                &loaded_session.aggregate,
+                &opt.aggregate.name(),
+            ),
+        ]
+        .iter()
+        .filter(|(_, loaded, current)| loaded != current)
+        .map(|(name, _, _)| *name)
+        .collect();
+
+        if !mismatches.is_empty() {
+            event_send.send(AppEvent::Notify(format!(
+                "The loaded session has a different {}",
+                mismatches.join(", ")
+            )));
         }
+
+        let mut analyzer = worker.analyzer.lock().unwrap();
+        loaded_session.match_series(&analyzer.series_names());
+        analyzer.preload(loaded_session.samples);
+    }
+
+    let mut app = AppState::new(worker, extra_workers, event_send, cmd_string, opt);
+
+    // Exit with an error after the exports if the command couldn't be run
+    let mut succeeded = true;
+
+    if app.opt.no_ui {
+        succeeded = print_samples(&mut app, &event_recv)?;
+    } else {
+        let stdout = io::stdout().into_raw_mode()?;
+        let stdout = termion::screen::AlternateScreen::from(stdout);
+        let backend = TermionBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+        terminal.hide_cursor()?;
+
+        watch_resize(app.event_send.clone())?;
+
+        app.draw(&mut terminal)?;
+
+        for e in event_recv.iter() {
+            if app.process_event(e?, &mut terminal)? {
+                break;
+            }
+        }
+
+        // Restore the terminal before reporting errors
+        drop(terminal);
+    }
+
+    if app.opt.dump {
+        let samples = app.worker.analyzer.lock().unwrap().samples.clone();
+        let stdout = io::stdout();
+        export::write_columns(&mut stdout.lock(), &samples)?;
+    }
+
+    if let Some(path) = &app.opt.export_csv {
+        let analyzer = app.worker.analyzer.lock().unwrap();
+        if let Err(e) = write_csv_file(path, &analyzer.samples, analyzer.progress().as_ref()) {
+            eprintln!("error: failed to export the samples: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &app.opt.save_session {
+        if let Err(e) = session::save(path, &app.session()) {
+            eprintln!("error: failed to save the session: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if !succeeded {
+        std::process::exit(1);
     }
 
     Ok(())
 }
 
+/// Parse the command-line arguments, using the configuration file to provide
+/// default values.
+fn parse_opt() -> Opt {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+
+    // The profile must be known before the configuration file is loaded.
+    // Invalid arguments are reported by the final parse.
+    let profile = Opt::from_iter_safe(&args).ok().and_then(|opt| opt.profile);
+
+    let defaults = config::load_defaults(profile.as_deref()).unwrap_or_else(|e| {
+        eprintln!("error: failed to load the configuration file: {}", e);
+        std::process::exit(1);
+    });
+
+    let i = args.len().min(1);
+    args.splice(i..i, defaults.args);
+
+    let mut opt = Opt::from_iter(args);
+    if opt.cmd.is_empty() {
+        opt.cmd = defaults.cmd;
+    }
+    if opt.reads_stdin() && !opt.extra_cmds.is_empty() {
+        opt.cmd = vec![opt.extra_cmds.remove(0).into()];
+        opt.shell = true;
+    }
+    opt
+}
+
 enum AppEvent {
     Term(Event),
     Resize,
     Update,
+    /// Quit the application.
+    Quit,
+    /// Display a message for a short while.
+    Notify(String),
+    /// The worker has processed all input and won't send `Update` anymore.
+    Finished,
 }
 
 #[derive(Clone)]
@@ -105,75 +808,761 @@ fn start_event_loop(
 
 fn watch_resize(evt_send: AppEventSender) -> Result<(), io::Error> {
     use signal_hook::iterator::Signals;
-    let signals = Signals::new(&[signal_hook::SIGWINCH])?;
+    let signals = Signals::new([signal_hook::SIGWINCH])?;
     std::thread::spawn(move || {
         for _ in signals.forever() {
-            dbg!();
-            let _ = evt_send.send(AppEvent::Resize);
+            evt_send.send(AppEvent::Resize);
         }
     });
     Ok(())
 }
 
+fn quit_after(duration: Duration, evt_send: AppEventSender) {
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        evt_send.send(AppEvent::Quit);
+    });
+}
+
+/// Implements `--no-ui`. Print new samples as they arrive until the worker
+/// finishes or `AppEvent::Quit` is received. Returns `false` if the last run
+/// failed to produce output, which has been reported to stderr.
+fn print_samples(
+    app: &mut AppState,
+    event_recv: &mpsc::Receiver<Result<AppEvent, io::Error>>,
+) -> Result<bool, io::Error> {
+    let stdout = io::stdout();
+    let mut last_printed: Option<Instant> = None;
+
+    for e in event_recv.iter() {
+        let finished = match e? {
+            AppEvent::Update => false,
+            AppEvent::Finished => true,
+            AppEvent::Quit => break,
+            AppEvent::Notify(message) => {
+                eprintln!("{}", message);
+                continue;
+            }
+            AppEvent::Term(_) | AppEvent::Resize => continue,
+        };
+
+        // For `--notify`
+        app.update_done();
+
+        let worker = &app.worker;
+        let analyzer = worker.analyzer.lock().unwrap();
+        let progress = analyzer.progress();
+        let since = last_printed;
+        let new_samples = analyzer
+            .samples
+            .iter()
+            .filter(|sample| since.map_or(true, |since| sample.instant > since));
+        let mut out = stdout.lock();
+        for sample in new_samples {
+            // The progress is only known for the latest sample
+            let is_latest = analyzer.samples.back().map(|s| s.instant) == Some(sample.instant);
+            let progress = progress.as_ref().filter(|_| is_latest);
+            export::write_sample_json(&mut out, sample, progress)?;
+            last_printed = Some(sample.instant);
+        }
+        out.flush()?;
+        drop(analyzer);
+
+        if finished {
+            if let Some(Err(e)) = &*worker.last_output.lock().unwrap() {
+                eprintln!("error: {}", e);
+                return Ok(false);
+            }
+            break;
+        }
+    }
+    Ok(true)
+}
+
+/// Write `progress` for `--print-eta-json`.
+fn write_eta_json(file: &mut fs::File, progress: &analysis::Progress) -> Result<(), io::Error> {
+    // Write the object at once so that readers don't observe a partial one
+    let mut buf = Vec::new();
+    export::write_progress_json(&mut buf, progress)?;
+
+    if file.metadata()?.is_file() {
+        file.set_len(0)?;
+        file.seek(io::SeekFrom::Start(0))?;
+    }
+    file.write_all(&buf)
+}
+
 struct WorkerState {
     analyzer: &'static Mutex<analysis::Analyzer>,
     last_output: &'static Mutex<Option<runner::CmdResult>>,
+    /// The outputs of the slowest runs, slowest first. Up to
+    /// `Opt::keep_slowest` runs are retained.
+    slowest_outputs: &'static Mutex<Vec<runner::CmdOutput>>,
+    /// Requests an immediate run in watch mode.
+    rerun: mpsc::Sender<()>,
+    pause: &'static Pause,
 }
 
-fn start_worker(cfg: &mut Opt, evt_send: AppEventSender) -> WorkerState {
-    let analyzer: &_ = Box::leak(Box::new(Mutex::new(analysis::Analyzer::new())));
+/// Suspends the worker while the user pauses sampling. The worker blocks
+/// before processing the next output, which stops further runs in watch mode
+/// and stops reading stdin.
+#[derive(Default)]
+struct Pause {
+    /// When the sampling was paused, if it is.
+    paused_at: Mutex<Option<Instant>>,
+    resumed: Condvar,
+}
+
+impl Pause {
+    fn paused_at(&self) -> Option<Instant> {
+        *self.paused_at.lock().unwrap()
+    }
+
+    fn toggle(&self) {
+        let mut paused_at = self.paused_at.lock().unwrap();
+        *paused_at = match *paused_at {
+            Some(_) => None,
+            None => Some(Instant::now()),
+        };
+        self.resumed.notify_all();
+    }
+
+    /// Block the calling thread while paused.
+    fn wait(&self) {
+        let mut paused_at = self.paused_at.lock().unwrap();
+        while paused_at.is_some() {
+            paused_at = self.resumed.wait(paused_at).unwrap();
+        }
+    }
+}
+
+fn analysis_options(cfg: &Opt) -> analysis::Options {
+    analysis::Options {
+        autodetect: !cfg.no_autodetect,
+        max_mode: match (cfg.max_mode.as_str(), cfg.max) {
+            (_, Some(max)) => analysis::MaxMode::Fixed(max),
+            ("seen", None) => analysis::MaxMode::Seen,
+            ("first", None) => analysis::MaxMode::First,
+            _ => analysis::MaxMode::Captured,
+        },
+        parse_mode: cfg.parse_mode,
+        aggregate: cfg.aggregate,
+        debug: cfg.debug,
+        strip_ansi: !cfg.no_ansi,
+        line_match: cfg.line_match.clone(),
+        line_ignore: cfg.line_ignore.clone(),
+        annotate: cfg.annotate.clone(),
+        median: cfg.median,
+        reset_mode: cfg.reset_mode,
+        reject_outliers: cfg.reject_outliers,
+        min_sample_interval: cfg.min_sample_interval,
+        warmup: cfg.warmup,
+        meter: cfg.meter,
+        cumulative: cfg.cumulative,
+        invert: cfg.invert,
+        pattern: cfg.regex.clone(),
+        window: cfg.window,
+        smooth: cfg.smooth,
+        eta_window: cfg.eta_window,
+        stall_timeout: cfg.stall_timeout,
+        json: cfg.json.as_ref().map(|value| analysis::JsonPointers {
+            value: value.clone(),
+            max: cfg.json_max.clone(),
+        }),
+        kv: cfg.kv.clone(),
+        binary_prefixes: cfg.unit == unit::Unit::IBytes,
+    }
+}
+
+/// Write samples and the progress as CSV to `path`.
+fn write_csv_file<'a>(
+    path: &Path,
+    samples: impl IntoIterator<Item = &'a analysis::Sample>,
+    progress: Option<&analysis::Progress>,
+) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    if let Some(progress) = progress {
+        export::write_progress_comment(&mut out, progress)?;
+    }
+    export::write_csv(&mut out, samples)?;
+    io::Write::flush(&mut out)
+}
+
+/// Get the command to execute.
+fn command_args(cfg: &Opt) -> Vec<OsString> {
+    let cmd = cfg.cmd.clone();
+    if cfg.shell {
+        runner::shell_cmd(&cmd)
+    } else {
+        cmd
+    }
+}
+
+fn runner_options(cfg: &Opt, tee: Option<Arc<fs::File>>) -> runner::Options {
+    runner::Options {
+        encoding: cfg.encoding,
+        initial_delay: cfg.initial_delay,
+        interval: Arc::new(Mutex::new(cfg.interval)),
+        max_output_bytes: cfg.max_output_bytes,
+        interleave: cfg.interleave,
+        tee,
+        cwd: cfg.cwd.clone(),
+        env: cfg.env.clone(),
+    }
+}
+
+/// Implements `--render-svg`. Runs the command to completion without the
+/// user interface and writes the chart to `path`.
+fn render_svg(cfg: &Opt, path: &Path) -> Result<(), io::Error> {
+    let title = if cfg.shell {
+        runner::join_args(&cfg.cmd).to_string_lossy().into_owned()
+    } else {
+        cmd_to_string(&cfg.cmd)
+    };
+    let mut analyzer = analysis::Analyzer::new(analysis_options(cfg));
+    let cmd = command_args(cfg);
+
+    let mut result = Ok(());
+    runner::run_once(cmd, &runner_options(cfg, None), |output| match output {
+        Ok(output) => analyzer.process_output(&output),
+        Err(e) => result = Err(e),
+    });
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    svg::write_chart(
+        &mut file,
+        &title,
+        &analyzer.samples,
+        &cfg.unit,
+        cfg.precision,
+        cfg.smooth,
+    )?;
+    file.flush()
+}
+
+fn start_worker(
+    cfg: &Opt,
+    mut eta_json: Option<fs::File>,
+    tee: Option<Arc<fs::File>>,
+    evt_send: AppEventSender,
+) -> WorkerState {
+    let analyzer = analysis::Analyzer::new(analysis_options(cfg));
+    let analyzer: &_ = Box::leak(Box::new(Mutex::new(analyzer)));
     let last_output: &_ = Box::leak(Box::new(Mutex::new(None)));
+    let slowest_outputs: &_ = Box::leak(Box::new(Mutex::new(Vec::new())));
+    let pause: &Pause = Box::leak(Box::default());
+    let (rerun, rerun_recv) = mpsc::channel();
+
+    let cmd = command_args(cfg);
+    let options = runner_options(cfg, tee);
 
-    let cmd = std::mem::replace(&mut cfg.cmd, Vec::new());
+    if cfg.until_exit {
+        // Keep the elapsed time up-to-date even if the command is silent.
+        // Stop when it exits or fails to run.
+        let evt_send = evt_send.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(1));
+            match &*last_output.lock().unwrap() {
+                Some(Ok(runner::CmdOutput {
+                    status: Some(_), ..
+                }))
+                | Some(Err(_)) => break,
+                _ => {}
+            }
+            evt_send.send(AppEvent::Update);
+        });
+    }
 
+    let until_exit = cfg.until_exit;
+    let watch_file = cfg.watch_file.clone();
+    let keep_slowest = cfg.keep_slowest;
+    let success_codes = cfg.success_codes.clone();
+    let success_pattern = cfg.success_pattern.clone();
+    let mut hooks = hooks::Hooks::new(cfg.on_complete.clone(), cfg.on_threshold.clone());
     std::thread::spawn(move || {
-        runner::watch_cmd(cmd, |output| {
-            if let Ok(output) = &output {
-                analyzer.lock().unwrap().process_output(output);
+        let cb = |mut output: runner::CmdResult| {
+            pause.wait();
+
+            if let Ok(output) = &mut output {
+                if let Some(status) = output.status {
+                    output.success = Some(
+                        status
+                            .code()
+                            .map_or(false, |code| success_codes.contains(&code))
+                            && success_pattern.as_ref().map_or(true, |re| {
+                                re.is_match(&output.stdout) || re.is_match(&output.stderr)
+                            }),
+                    );
+                }
+
+                let mut analyzer = analyzer.lock().unwrap();
+                analyzer.process_output(output);
+
+                if let Some(interval) = analyzer.take_requested_interval() {
+                    *options.interval.lock().unwrap() = interval;
+                }
+
+                if let (Some(file), Some(progress)) = (&mut eta_json, analyzer.progress()) {
+                    // The consumer may have gone away; that shouldn't stop us
+                    let _ = write_eta_json(file, &progress);
+                }
+
+                let succeeded = until_exit && output.success == Some(true);
+                for e in hooks.update(&analyzer, succeeded) {
+                    evt_send.send(AppEvent::Notify(format!("Failed to run a hook: {}", e)));
+                }
+                drop(analyzer);
+
+                if keep_slowest > 0 && output.duration.is_some() {
+                    let mut slowest_outputs = slowest_outputs.lock().unwrap();
+                    slowest_outputs.push(output.clone());
+                    slowest_outputs.sort_by_key(|output| std::cmp::Reverse(output.duration));
+                    slowest_outputs.truncate(keep_slowest);
+                }
             }
 
             *last_output.lock().unwrap() = Some(output);
 
-            let _ = evt_send.send(AppEvent::Update);
-        });
+            evt_send.send(AppEvent::Update);
+        };
+
+        if let Some(path) = watch_file {
+            runner::watch_file(&path, &options, rerun_recv, cb);
+        } else if cmd.is_empty() {
+            runner::read_stdin(&options, cb);
+            evt_send.send(AppEvent::Notify("Reached the end of stdin".to_owned()));
+        } else if until_exit {
+            runner::run_once(cmd, &options, cb);
+        } else {
+            runner::watch_cmd(cmd, &options, rerun_recv, cb);
+        }
+        evt_send.send(AppEvent::Finished);
     });
 
     WorkerState {
         analyzer,
         last_output,
+        slowest_outputs,
+        rerun,
+        pause,
     }
 }
 
+/// The output panes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Stdout,
+    Stderr,
+}
+
 struct AppState {
     worker: WorkerState,
+    /// The workers of the `--cmd`s other than the main command, along with
+    /// the commands.
+    extra_workers: Vec<(String, WorkerState)>,
+    event_send: AppEventSender,
     show_help: bool,
+    /// The scroll offset (in lines) of the help window. Clamped when drawn.
+    help_scroll: u16,
+    /// Show the rate histogram in place of the time series chart.
+    show_histogram: bool,
+    /// Show the stderr pane. If hidden, stdout takes up its room.
+    show_stderr: bool,
+    /// What the time series chart plots.
+    chart_mode: draw::ChartMode,
+    /// Use a logarithmic scale for the y axes of the time series charts.
+    log_scale: bool,
+    /// The magnification of the time axis. `1.0` shows the full time span.
+    zoom: f64,
+    /// How far (in seconds) the right edge of the chart is from the latest
+    /// sample. Clamped when drawn.
+    pan: f64,
+    /// The relative widths of the stdout and stderr panes.
+    output_split: draw::OutputSplit,
+    /// The scroll offsets (in lines) of the output panes, indexed by `Pane`.
+    /// Clamped when drawn.
+    output_scroll: [u16; 2],
+    /// The output pane that receives the scroll commands.
+    focused_pane: Pane,
+    /// The height (in lines) of the focused output pane when it was last
+    /// drawn.
+    output_pane_height: u16,
+    /// The index into `WorkerState::slowest_outputs` of the run displayed in
+    /// place of the latest one.
+    viewed_run: Option<usize>,
+    /// `Opt::theme` with the gauge colors overridden by the options.
+    theme: theme::Theme,
+    /// The command has exited with `--until-exit`, or the progress has reached
+    /// the maximum value. The charts are replaced with a summary.
+    done: bool,
+    /// A message to display and when it was posted.
+    notification: Option<(String, Instant)>,
     cmd_string: String,
+    opt: Opt,
 }
 
 impl AppState {
+    fn new(
+        worker: WorkerState,
+        extra_workers: Vec<(String, WorkerState)>,
+        event_send: AppEventSender,
+        cmd_string: String,
+        opt: Opt,
+    ) -> Self {
+        let mut theme = opt.theme.unwrap_or_else(theme::Theme::from_env);
+        theme.gauge = opt.gauge_color.unwrap_or(theme.gauge);
+        theme.gauge_background = opt.gauge_background.unwrap_or(theme.gauge_background);
+
+        Self {
+            worker,
+            extra_workers,
+            event_send,
+            show_help: false,
+            help_scroll: 0,
+            show_histogram: false,
+            show_stderr: true,
+            chart_mode: opt.chart,
+            log_scale: opt.log_scale,
+            zoom: 1.0,
+            pan: 0.0,
+            output_split: opt.output_split,
+            output_scroll: [0; 2],
+            theme,
+            focused_pane: Pane::Stdout,
+            output_pane_height: 0,
+            viewed_run: None,
+            done: false,
+            notification: None,
+            cmd_string,
+            opt,
+        }
+    }
+
     fn process_event(
         &mut self,
         e: AppEvent,
         terminal: &mut Terminal<impl tui::backend::Backend>,
     ) -> Result<bool, io::Error> {
         match e {
-            AppEvent::Term(Event::Key(Key::Ctrl('c')))
-            | AppEvent::Term(Event::Key(Key::Char('q')))
-            | AppEvent::Term(Event::Key(Key::Esc)) => {
+            AppEvent::Term(Event::Key(key)) if self.is_quit_key(key) => {
                 // Quit
                 return Ok(true);
             }
-            AppEvent::Term(Event::Key(Key::Char('h'))) => {
-                self.show_help = !self.show_help;
-                self.draw(terminal)?;
+            AppEvent::Quit => return Ok(true),
+            AppEvent::Term(Event::Key(key)) => {
+                if let Some(action) = keys::action(key) {
+                    self.perform(action, terminal)?;
+                }
             }
             AppEvent::Term(_) => {}
-            AppEvent::Resize | AppEvent::Update => {
+            AppEvent::Resize => {
+                self.draw(terminal)?;
+            }
+            AppEvent::Update => {
+                self.update_done();
+                self.draw(terminal)?;
+            }
+            AppEvent::Notify(message) => {
+                self.notification = Some((message, Instant::now()));
                 self.draw(terminal)?;
             }
+            AppEvent::Finished => {}
         }
         Ok(false)
     }
+
+    fn perform(
+        &mut self,
+        action: keys::Action,
+        terminal: &mut Terminal<impl tui::backend::Backend>,
+    ) -> Result<(), io::Error> {
+        use keys::Action;
+        match action {
+            Action::ToggleHelp => {
+                self.show_help = !self.show_help;
+                self.help_scroll = 0;
+            }
+            Action::ScrollUp if self.show_help => {
+                self.help_scroll = self.help_scroll.saturating_sub(1);
+            }
+            Action::ScrollDown if self.show_help => {
+                // Clamped when drawn
+                self.help_scroll = self.help_scroll.saturating_add(1);
+            }
+            Action::ScrollUp => self.scroll_output(-1),
+            Action::ScrollDown => self.scroll_output(1),
+            Action::ScrollPageUp => self.scroll_output(-self.output_page_size()),
+            Action::ScrollPageDown => self.scroll_output(self.output_page_size()),
+            Action::FocusNextPane => {
+                self.focused_pane = match self.focused_pane {
+                    Pane::Stdout => Pane::Stderr,
+                    Pane::Stderr => Pane::Stdout,
+                };
+            }
+            Action::ToggleHistogram => {
+                self.show_histogram = !self.show_histogram;
+            }
+            Action::ToggleStderr => {
+                self.show_stderr = !self.show_stderr;
+            }
+            Action::CycleOutputSplit => {
+                self.output_split = self.output_split.next();
+            }
+            Action::ScrollToTop => {
+                self.output_scroll[self.focused_pane as usize] = 0;
+            }
+            Action::ScrollToBottom => {
+                // Clamped to the bottom when drawn
+                self.output_scroll[self.focused_pane as usize] = u16::MAX;
+            }
+            Action::ZoomIn => {
+                let full_span = self.full_time_span(&self.worker.analyzer.lock().unwrap().samples);
+                let max_zoom = (full_span / draw::MIN_TIME_SCALE).max(1.0);
+                self.zoom = (self.zoom * 2.0).min(max_zoom);
+            }
+            Action::ZoomOut => {
+                self.zoom = (self.zoom / 2.0).max(1.0);
+            }
+            Action::CycleChartMode => {
+                self.chart_mode = self.chart_mode.next();
+            }
+            Action::ToggleLogScale => {
+                self.log_scale = !self.log_scale;
+            }
+            Action::ResetZoom => {
+                self.zoom = 1.0;
+                self.pan = 0.0;
+            }
+            Action::PanLeft => {
+                self.pan += self.visible_time_span() * draw::PAN_STEP;
+            }
+            Action::PanRight => {
+                self.pan = (self.pan - self.visible_time_span() * draw::PAN_STEP).max(0.0);
+            }
+            Action::TogglePause => {
+                self.worker.pause.toggle();
+                for (_, worker) in &self.extra_workers {
+                    worker.pause.toggle();
+                }
+            }
+            Action::SaveSnapshot => {
+                self.save_snapshot();
+                return Ok(());
+            }
+            Action::ExportCsv => {
+                self.export_csv();
+                return Ok(());
+            }
+            Action::Rerun => {
+                // The worker may have stopped (`--until-exit`)
+                let _ = self.worker.rerun.send(());
+                for (_, worker) in &self.extra_workers {
+                    let _ = worker.rerun.send(());
+                }
+                return Ok(());
+            }
+            Action::CopyStatus => {
+                self.copy_status();
+                return Ok(());
+            }
+            Action::CycleSlowestRuns => {
+                let num_runs = self.worker.slowest_outputs.lock().unwrap().len();
+                self.viewed_run = match self.viewed_run {
+                    None if num_runs > 0 => Some(0),
+                    Some(i) if i + 1 < num_runs => Some(i + 1),
+                    _ => None,
+                };
+                if num_runs == 0 {
+                    self.notification = Some((
+                        "No runs have been retained. See `--keep-slowest`.".to_owned(),
+                        Instant::now(),
+                    ));
+                }
+            }
+        }
+        self.draw(terminal)
+    }
+
+    fn is_quit_key(&self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => c == self.opt.quit_key,
+            Key::Esc => !self.opt.no_esc_quit,
+            Key::Ctrl('c') => !self.opt.no_ctrl_c_quit,
+            _ => false,
+        }
+    }
+
+    /// Describe the keys that quit the application for the help window.
+    fn quit_keys_label(&self) -> String {
+        let mut keys = Vec::new();
+        if !self.opt.no_esc_quit {
+            keys.push("ESC".to_owned());
+        }
+        keys.push(match self.opt.quit_key {
+            ' ' => "SPACE".to_owned(),
+            '\t' => "TAB".to_owned(),
+            c => c.to_string(),
+        });
+        if !self.opt.no_ctrl_c_quit {
+            keys.push("^C".to_owned());
+        }
+        keys.join(" ")
+    }
+
+    /// Write the current samples and progress to a timestamped file in the
+    /// current directory. The result is reported through a notification.
+    fn save_snapshot(&self) {
+        let path = format!(
+            "pvfilt-{}.csv",
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        );
+        self.write_csv_in_background(path.into(), "a snapshot");
+    }
+
+    /// Write the current samples and progress to the file given by
+    /// `--export-csv`.
+    fn export_csv(&self) {
+        if let Some(path) = &self.opt.export_csv {
+            self.write_csv_in_background(path.clone(), "the samples");
+        } else {
+            self.event_send.send(AppEvent::Notify(
+                "No file to export to. See `--export-csv`.".to_owned(),
+            ));
+        }
+    }
+
+    /// Write the current samples and progress to `path` without blocking the
+    /// user interface. The result is reported through a notification that
+    /// refers to the contents as `what`.
+    fn write_csv_in_background(&self, path: PathBuf, what: &'static str) {
+        let analyzer = self.worker.analyzer.lock().unwrap();
+        let samples: Vec<_> = analyzer.samples.iter().cloned().collect();
+        let progress = analyzer.progress();
+        drop(analyzer);
+
+        let evt_send = self.event_send.clone();
+
+        std::thread::spawn(move || {
+            let result = write_csv_file(&path, &samples, progress.as_ref());
+
+            evt_send.send(AppEvent::Notify(match result {
+                Ok(()) => format!("Saved {} to {}", what, path.display()),
+                Err(e) => format!("Failed to save {}: {}", what, e),
+            }));
+        });
+    }
+
+    /// Copy the status line to the clipboard.
+    fn copy_status(&self) {
+        let line = match self.status_line() {
+            Some(line) => line,
+            None => {
+                self.event_send
+                    .send(AppEvent::Notify("Nothing to copy yet".to_owned()));
+                return;
+            }
+        };
+        let evt_send = self.event_send.clone();
+
+        // The clipboard utility may take a while
+        std::thread::spawn(move || {
+            evt_send.send(AppEvent::Notify(match clipboard::copy(&line) {
+                Ok(()) => format!("Copied \"{}\"", line),
+                Err(e) => format!("Failed to copy to the clipboard: {}", e),
+            }));
+        });
+    }
+
+    /// Scroll the focused output pane by `lines`.
+    fn scroll_output(&mut self, lines: i32) {
+        // Clamped when drawn
+        let scroll = &mut self.output_scroll[self.focused_pane as usize];
+        *scroll = (i32::from(*scroll) + lines).clamp(0, i32::from(u16::MAX)) as u16;
+    }
+
+    /// The number of lines scrolled by a page, leaving a line of context.
+    fn output_page_size(&self) -> i32 {
+        (i32::from(self.output_pane_height) - 1).max(1)
+    }
+
+    /// Capture the current samples for `--save-session`.
+    fn session(&self) -> session::Session {
+        let analyzer = self.worker.analyzer.lock().unwrap();
+        session::Session {
+            command: self.cmd_string.clone(),
+            parse_mode: self.opt.parse_mode.name().to_owned(),
+            aggregate: self.opt.aggregate.name(),
+            series_names: (analyzer.series_names().into_iter())
+                .map(str::to_owned)
+                .collect(),
+            samples: analyzer.samples.iter().cloned().collect(),
+        }
+    }
+
+    /// Enter the done state if the run is complete.
+    fn update_done(&mut self) {
+        let reached_max = self
+            .worker
+            .analyzer
+            .lock()
+            .unwrap()
+            .progress()
+            .map_or(false, |progress| {
+                progress.max.map_or(false, |max| progress.value >= max)
+            });
+        let was_done = self.done;
+        self.done = self.done || reached_max || self.has_exited();
+
+        if self.done && !was_done && self.opt.notify {
+            let succeeded = matches!(
+                &*self.worker.last_output.lock().unwrap(),
+                Some(Ok(output)) if output.success == Some(true)
+            );
+            if reached_max {
+                self.notify_desktop("Reached the maximum value");
+            } else if succeeded {
+                self.notify_desktop("The command succeeded");
+            }
+        }
+    }
+
+    /// Show a desktop notification about the command for `--notify`.
+    fn notify_desktop(&self, message: &str) {
+        let summary = format!("pvfilt: {}", message);
+        let body = self.cmd_string.clone();
+        let evt_send = self.event_send.clone();
+
+        if self.opt.no_ui {
+            // There's no UI to block, and the process may exit soon
+            if let Err(e) = desktop::notify(&summary, &body) {
+                eprintln!("Failed to show a desktop notification: {}", e);
+            }
+            return;
+        }
+
+        // Don't block the UI on the notification utility
+        std::thread::spawn(move || {
+            if let Err(e) = desktop::notify(&summary, &body) {
+                evt_send.send(AppEvent::Notify(format!(
+                    "Failed to show a desktop notification: {}",
+                    e
+                )));
+            }
+        });
+    }
+
+    /// Get the notification to display, if any.
+    fn notification(&self) -> Option<&str> {
+        const NOTIFICATION_DURATION: Duration = Duration::from_secs(5);
+        self.notification
+            .as_ref()
+            .filter(|(_, posted)| posted.elapsed() < NOTIFICATION_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
 }
 
 fn cmd_to_string(cmd: &[OsString]) -> String {
@@ -215,7 +1604,7 @@ fn cmd_to_string(cmd: &[OsString]) -> String {
                 .find(|c: char| c.is_control() || map.iter().find(|(from, _)| *from == c).is_some())
             {
                 out.push_str(&s[0..i]);
-                let ch = s[i..].chars().nth(0).unwrap();
+                let ch = s[i..].chars().next().unwrap();
 
                 if let Some((_, map_to)) = map.iter().find(|(from, _)| *from == ch) {
                     out.push_str(map_to);
@@ -1,32 +1,915 @@
 use std::{
     ffi::OsString,
+    fmt, fs,
+    io::{self, Read, Write},
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
     process::{Command, ExitStatus, Stdio},
-    time::Duration,
+    str::FromStr,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
-pub type CmdResult = Result<CmdOutput, std::io::Error>;
+pub type CmdResult = Result<CmdOutput, RunError>;
 
+/// The reason a run didn't produce any output.
+#[derive(Debug)]
+pub enum RunError {
+    /// The command couldn't be started.
+    Spawn { program: OsString, error: io::Error },
+    /// Waiting for the command to exit failed.
+    Wait(io::Error),
+    /// The file couldn't be read in `watch_file`.
+    Read { path: PathBuf, error: io::Error },
+    /// stdin couldn't be read in `read_stdin`.
+    Stdin(io::Error),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Spawn { program, error } => {
+                let program = program.to_string_lossy();
+                match error.kind() {
+                    io::ErrorKind::NotFound => write!(f, "command not found: {}", program),
+                    io::ErrorKind::PermissionDenied => {
+                        write!(f, "permission denied: {}", program)
+                    }
+                    _ => write!(f, "failed to execute {}: {}", program, error),
+                }
+            }
+            RunError::Wait(error) => write!(f, "failed to wait for the command: {}", error),
+            RunError::Read { path, error } => match error.kind() {
+                io::ErrorKind::NotFound => write!(f, "no such file: {}", path.display()),
+                _ => write!(f, "failed to read {}: {}", path.display(), error),
+            },
+            RunError::Stdin(error) => write!(f, "failed to read stdin: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RunError::Spawn { error, .. }
+            | RunError::Wait(error)
+            | RunError::Read { error, .. }
+            | RunError::Stdin(error) => Some(error),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct CmdOutput {
-    pub status: ExitStatus,
+    /// The exit status. `None` if the command is still running.
+    pub status: Option<ExitStatus>,
+    /// `stdout_raw` decoded by `Encoding::decode`.
     pub stdout: String,
+    /// `stderr_raw` decoded by `Encoding::decode`.
     pub stderr: String,
+    /// The latest part of stdout retained within `Options::max_output_bytes`.
+    pub stdout_raw: Vec<u8>,
+    /// The latest part of stderr retained within `Options::max_output_bytes`.
+    pub stderr_raw: Vec<u8>,
+    /// The length of the decoded stdout discarded before `stdout`. An offset
+    /// into the entire decoded stdout minus this is an offset into `stdout`.
+    pub stdout_offset: usize,
+    /// Some of stdout was discarded by `Options::max_output_bytes`.
+    pub stdout_truncated: bool,
+    /// Some of stderr was discarded by `Options::max_output_bytes`.
+    pub stderr_truncated: bool,
+    /// The number of bytes written to stdout, including the ones discarded
+    /// by `Options::max_output_bytes`.
+    pub stdout_len: usize,
+    /// The number of line terminators written to stdout, including the ones
+    /// discarded by `Options::max_output_bytes`.
+    pub stdout_num_lines: usize,
+    /// The lines from the both streams in the order they were written. Only
+    /// recorded if `Options::interleave` is set.
+    pub lines: Vec<OutputLine>,
+    /// When the run started.
+    pub started: Instant,
+    /// When the run started, in the wall-clock time.
+    pub start_time: SystemTime,
+    /// How long the run took. `None` if the command is still running.
+    pub duration: Option<Duration>,
+    /// Whether the run is considered successful. `None` if the command is
+    /// still running. Initialized from `ExitStatus::success`.
+    pub success: Option<bool>,
+}
+
+impl CmdOutput {
+    /// The time elapsed since the run started, or the run's duration if the
+    /// command has exited.
+    pub fn elapsed(&self) -> Duration {
+        self.duration.unwrap_or_else(|| self.started.elapsed())
+    }
+}
+
+/// A line of a command's output.
+#[derive(Clone)]
+pub struct OutputLine {
+    pub stream: Stream,
+    /// When the first byte of the line arrived, relative to the start of the
+    /// run.
+    pub time: Duration,
+    /// The line decoded by `Encoding::decode`, without the line terminator.
+    pub text: String,
+}
+
+/// Options controlling how a command is run.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub encoding: Encoding,
+    /// The delay before the first run.
+    pub initial_delay: Duration,
+    /// The delay between the end of a run and the start of the next one. Can
+    /// be changed while running.
+    pub interval: Arc<Mutex<Duration>>,
+    /// The number of bytes retained from each stream per run. Once exceeded,
+    /// the oldest lines are discarded, and so is the part of a line beyond
+    /// this length.
+    pub max_output_bytes: usize,
+    /// Record `CmdOutput::lines`.
+    pub interleave: bool,
+    /// The file to copy the raw output to.
+    pub tee: Option<Arc<fs::File>>,
+    /// The working directory of the command. Inherited if `None`.
+    pub cwd: Option<PathBuf>,
+    /// The environment variables set in addition to the inherited ones.
+    pub env: Vec<(String, String)>,
+}
+
+/// The same as the command-line defaults.
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8Lossy,
+            initial_delay: Duration::from_secs(0),
+            interval: Arc::new(Mutex::new(Duration::from_secs(1))),
+            max_output_bytes: 262144,
+            interleave: false,
+            tee: None,
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+}
+
+/// Specifies how a command's output is decoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// UTF-8. Invalid sequences are replaced with U+FFFD.
+    Utf8Lossy,
+    /// ISO 8859-1. Every byte is mapped to the code point of the same value.
+    Latin1,
+    /// Decoded as `Utf8Lossy` but displayed as a hex dump.
+    RawHex,
+}
+
+impl Encoding {
+    pub const NAMES: &'static [&'static str] = &["utf8-lossy", "latin1", "raw-hex"];
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8Lossy | Encoding::RawHex => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        }
+    }
+
+    /// Decode `bytes` except for an incomplete character at the end, which
+    /// may be completed by the bytes that follow. Returns the decoded text and
+    /// the number of bytes consumed.
+    fn decode_prefix(self, bytes: &[u8]) -> (String, usize) {
+        if self == Encoding::Latin1 {
+            return (self.decode(bytes), bytes.len());
+        }
+
+        let mut text = String::new();
+        let mut rest = bytes;
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    return (text, bytes.len());
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &invalid[len..];
+                        }
+                        None => return (text, bytes.len() - invalid.len()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utf8-lossy" => Ok(Encoding::Utf8Lossy),
+            "latin1" => Ok(Encoding::Latin1),
+            "raw-hex" => Ok(Encoding::RawHex),
+            _ => Err(format!("unknown encoding: {}", s)),
+        }
+    }
+}
+
+/// Construct a command that runs the given arguments, joined by spaces, as a
+/// shell script. `$SHELL` is used if set, `/bin/sh` otherwise.
+pub fn shell_cmd(cmd: &[OsString]) -> Vec<OsString> {
+    let shell = std::env::var_os("SHELL")
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "/bin/sh".into());
+
+    vec![shell, "-c".into(), join_args(cmd)]
+}
+
+/// Parse an environment variable assignment in the `KEY=VAL` format.
+pub fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(i) if i > 0 => Ok((s[..i].to_owned(), s[i + 1..].to_owned())),
+        _ => Err(format!("expected KEY=VAL: {}", s)),
+    }
+}
+
+/// Parse an interval between runs given in seconds (e.g., `0.5`) or as a
+/// duration (e.g., `500ms`, `2m`).
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = match s.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Duration::from_secs_f64(secs),
+        Ok(_) => return Err(format!("invalid interval: {}", s)),
+        Err(_) => humantime::parse_duration(s).map_err(|e| e.to_string())?,
+    };
+    if interval == Duration::from_secs(0) {
+        return Err("the interval must be positive".to_owned());
+    }
+    Ok(interval)
+}
+
+/// Join arguments by spaces without any quoting.
+pub fn join_args(cmd: &[OsString]) -> OsString {
+    let mut script = OsString::new();
+    for (i, arg) in cmd.iter().enumerate() {
+        if i > 0 {
+            script.push(" ");
+        }
+        script.push(arg);
+    }
+    script
 }
 
-pub fn watch_cmd(cmd: Vec<OsString>, mut cb: impl FnMut(CmdResult)) {
+/// Run the command repeatedly. A message sent through `rerun` cuts the
+/// current delay short.
+pub fn watch_cmd(
+    cmd: Vec<OsString>,
+    options: &Options,
+    rerun: mpsc::Receiver<()>,
+    mut cb: impl FnMut(CmdResult),
+) {
+    sleep_unless_rerun(options.initial_delay, &rerun);
+
     loop {
-        let child = Command::new(&cmd[0])
-            .args(&cmd[1..])
-            .stdout(Stdio::piped())
-            .spawn();
+        write_tee_separator(options);
+        run_cmd(&cmd, options, &mut cb);
 
-        let output = child.and_then(|child| child.wait_with_output());
+        sleep_unless_rerun(*options.interval.lock().unwrap(), &rerun);
+    }
+}
 
-        cb(output.map(|output| CmdOutput {
-            status: output.status,
-            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
-            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+/// Run the command only once, streaming its output until it exits.
+pub fn run_once(cmd: Vec<OsString>, options: &Options, mut cb: impl FnMut(CmdResult)) {
+    std::thread::sleep(options.initial_delay);
+
+    run_cmd(&cmd, options, &mut cb);
+}
+
+/// Read the entire file periodically. Each read is reported as the output of
+/// a command that exited successfully, so files that are rewritten as a whole
+/// (rather than appended to) are processed correctly. Since the path is
+/// reopened for every read, a rotated (renamed and recreated) or truncated
+/// file is picked up without special handling. A message sent through
+/// `rerun` cuts the current delay short.
+pub fn watch_file(
+    path: &Path,
+    options: &Options,
+    rerun: mpsc::Receiver<()>,
+    mut cb: impl FnMut(CmdResult),
+) {
+    sleep_unless_rerun(options.initial_delay, &rerun);
+
+    loop {
+        let started = Instant::now();
+        let result = fs::read(path).map_err(|error| RunError::Read {
+            path: path.to_owned(),
+            error,
+        });
+        cb(result.map(|bytes| {
+            write_tee_separator(options);
+            write_tee(options, &bytes);
+
+            // Only the latest lines of a large file are retained
+            let mut contents = Capture::default();
+            contents.append(&bytes, options.max_output_bytes, options.encoding);
+            contents.mark_reported(options.max_output_bytes);
+            let status = ExitStatus::from_raw(0);
+            let empty = Capture::default();
+            let lines = Lines::default();
+            make_output(
+                Some(status),
+                started,
+                options.encoding,
+                [&contents, &empty],
+                &lines,
+            )
         }));
 
-        std::thread::sleep(Duration::from_secs(1));
+        sleep_unless_rerun(*options.interval.lock().unwrap(), &rerun);
+    }
+}
+
+/// Read stdin until EOF, reporting it as the output of a running command.
+/// Only the latest lines are retained, but every line is reported before
+/// being discarded, so input of any length can be followed. Each report
+/// consists of the lines read at once, from which `Aggregate::Latest` takes
+/// the newest value.
+pub fn read_stdin(options: &Options, cb: impl FnMut(CmdResult)) {
+    let stdin = io::stdin();
+    read_input(stdin.lock(), options, cb);
+}
+
+/// The implementation of `read_stdin`.
+fn read_input(mut input: impl Read, options: &Options, mut cb: impl FnMut(CmdResult)) {
+    let mut buf = vec![0u8; 4096];
+    let started = Instant::now();
+    let mut contents = Capture::default();
+    let empty = Capture::default();
+    let lines = Lines::default();
+
+    loop {
+        let len = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return cb(Err(RunError::Stdin(e))),
+        };
+        write_tee(options, &buf[..len]);
+        contents.append(&buf[..len], options.max_output_bytes, options.encoding);
+
+        cb(Ok(make_output(
+            None,
+            started,
+            options.encoding,
+            [&contents, &empty],
+            &lines,
+        )));
+        contents.mark_reported(options.max_output_bytes);
+    }
+
+    let status = ExitStatus::from_raw(0);
+    cb(Ok(make_output(
+        Some(status),
+        started,
+        options.encoding,
+        [&contents, &empty],
+        &lines,
+    )));
+}
+
+/// Sleep for `duration` or until a message is received through `rerun`.
+fn sleep_unless_rerun(duration: Duration, rerun: &mpsc::Receiver<()>) {
+    match rerun.recv_timeout(duration) {
+        Ok(()) => {
+            // Requests made in the meantime are satisfied by the next run
+            while rerun.try_recv().is_ok() {}
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => {}
+        Err(mpsc::RecvTimeoutError::Disconnected) => std::thread::sleep(duration),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Run the command once. `cb` is called whenever the command produces output
+/// and once more when it exits.
+fn run_cmd(cmd: &[OsString], options: &Options, cb: &mut impl FnMut(CmdResult)) {
+    let started = Instant::now();
+    let mut command = Command::new(&cmd[0]);
+    command
+        .args(&cmd[1..])
+        .envs(options.env.iter().map(|(key, value)| (key, value)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    let child = command.spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            return cb(Err(RunError::Spawn {
+                program: cmd[0].clone(),
+                error,
+            }))
+        }
+    };
+
+    // Read the both streams concurrently so that neither of them can fill up
+    // the pipe buffer and block the child
+    let (send, recv) = mpsc::channel();
+    spawn_reader(child.stdout.take().unwrap(), Stream::Stdout, send.clone());
+    spawn_reader(child.stderr.take().unwrap(), Stream::Stderr, send);
+
+    let mut captures = [Capture::default(), Capture::default()];
+    let mut lines = Lines::default();
+    // This loop ends when both readers reach EOF
+    while let Ok(chunk) = recv.recv() {
+        append_chunk(chunk, started, options, &mut captures, &mut lines);
+
+        // Coalesce chunks that have already arrived, but not so many that
+        // unreported lines would have to be discarded
+        while captures
+            .iter()
+            .all(|capture| capture.unreported_len() <= options.max_output_bytes / 2)
+        {
+            match recv.try_recv() {
+                Ok(chunk) => append_chunk(chunk, started, options, &mut captures, &mut lines),
+                Err(_) => break,
+            }
+        }
+
+        let [stdout, stderr] = &captures;
+        cb(Ok(make_output(
+            None,
+            started,
+            options.encoding,
+            [stdout, stderr],
+            &lines,
+        )));
+        for capture in &mut captures {
+            capture.mark_reported(options.max_output_bytes);
+        }
+    }
+
+    let status = child.wait();
+
+    let [stdout, stderr] = &captures;
+    cb(status.map_err(RunError::Wait).map(|status| {
+        make_output(
+            Some(status),
+            started,
+            options.encoding,
+            [stdout, stderr],
+            &lines,
+        )
+    }));
+}
+
+fn spawn_reader(
+    mut reader: impl Read + Send + 'static,
+    stream: Stream,
+    send: mpsc::Sender<(Stream, Instant, Vec<u8>)>,
+) {
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Ok(0) | Err(_) => break,
+                Ok(num_bytes) => {
+                    if send
+                        .send((stream, Instant::now(), buf[..num_bytes].to_vec()))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn append_chunk(
+    (stream, instant, chunk): (Stream, Instant, Vec<u8>),
+    started: Instant,
+    options: &Options,
+    captures: &mut [Capture; 2],
+    lines: &mut Lines,
+) {
+    write_tee(options, &chunk);
+
+    let capture = &mut captures[stream as usize];
+    if options.interleave {
+        // Only the beginning of the output is interleaved
+        let room = options.max_output_bytes.saturating_sub(capture.total_len);
+        let time = instant.saturating_duration_since(started);
+        lines.append(
+            stream,
+            time,
+            &chunk[..chunk.len().min(room)],
+            options.encoding,
+        );
+    }
+    capture.append(&chunk, options.max_output_bytes, options.encoding);
+}
+
+/// Copy a chunk of the output to `Options::tee`.
+fn write_tee(options: &Options, chunk: &[u8]) {
+    if let Some(file) = &options.tee {
+        // Don't let a full disk interrupt the monitoring
+        let _ = (&**file).write_all(chunk);
+    }
+}
+
+/// Write a line marking the start of a run to `Options::tee`.
+fn write_tee_separator(options: &Options) {
+    let separator = format!(
+        "==> {} <==\n",
+        humantime::format_rfc3339_seconds(SystemTime::now())
+    );
+    write_tee(options, separator.as_bytes());
+}
+
+/// The output captured from a stream. Only the latest lines are retained, so
+/// the output of a long-running command can be followed indefinitely.
+#[derive(Default)]
+struct Capture {
+    bytes: Vec<u8>,
+    /// `bytes` decoded, except for the last `bytes.len() - decoded_len` bytes,
+    /// which are an incomplete character. Decoded as they arrive so that a
+    /// long output isn't decoded over and over.
+    text: String,
+    decoded_len: usize,
+    /// The index in `bytes` of the start of the last line, which may be
+    /// incomplete.
+    line_start: usize,
+    /// The length of the decoded text of the lines discarded from the front.
+    discarded_len: usize,
+    /// The length of `bytes` that has been reported in a `CmdOutput`. Lines
+    /// are discarded only after being reported so that `Analyzer` sees every
+    /// line.
+    reported_len: usize,
+    /// Some of the output was discarded.
+    truncated: bool,
+    /// The number of bytes received, including the discarded ones.
+    total_len: usize,
+    /// The number of line terminators received, including the discarded ones.
+    num_lines: usize,
+}
+
+impl Capture {
+    /// Append `chunk`. The part of a line beyond `limit` bytes is discarded,
+    /// and so are the oldest reported lines (see `discard_lines`).
+    fn append(&mut self, mut chunk: &[u8], limit: usize, encoding: Encoding) {
+        self.total_len += chunk.len();
+        self.num_lines += chunk.iter().filter(|&&b| b == b'\n').count();
+
+        while !chunk.is_empty() {
+            let (line, rest) = match chunk.iter().position(|&b| b == b'\n') {
+                Some(i) => (&chunk[..i], Some(&chunk[i + 1..])),
+                None => (chunk, None),
+            };
+
+            let room = limit.saturating_sub(self.bytes.len() - self.line_start);
+            if line.len() > room {
+                self.truncated = true;
+            }
+            self.bytes.extend_from_slice(&line[..line.len().min(room)]);
+
+            match rest {
+                Some(rest) => {
+                    self.bytes.push(b'\n');
+                    self.line_start = self.bytes.len();
+                    chunk = rest;
+                }
+                None => break,
+            }
+        }
+
+        let (text, len) = encoding.decode_prefix(&self.bytes[self.decoded_len..]);
+        self.text.push_str(&text);
+        self.decoded_len += len;
+
+        self.discard_lines(limit);
+    }
+
+    /// Record that the retained output has been reported, and discard the
+    /// oldest lines if needed.
+    fn mark_reported(&mut self, limit: usize) {
+        self.reported_len = self.bytes.len();
+        self.discard_lines(limit);
+    }
+
+    /// The length of the output that has not been reported yet.
+    fn unreported_len(&self) -> usize {
+        self.bytes.len() - self.reported_len
+    }
+
+    /// If the retained bytes exceed `limit`, discard the oldest complete lines
+    /// that have been reported until half of `limit` or less remains.
+    fn discard_lines(&mut self, limit: usize) {
+        if self.bytes.len() <= limit {
+            return;
+        }
+
+        let bound = self.line_start.min(self.reported_len);
+        let target = self.bytes.len() - limit / 2;
+        let is_line_start = |i: usize| i == 0 || self.bytes[i - 1] == b'\n';
+        let cut = (target..=bound)
+            .find(|&i| is_line_start(i))
+            .or_else(|| (0..=bound).rev().find(|&i| is_line_start(i)))
+            .unwrap_or(0);
+        if cut == 0 {
+            return;
+        }
+
+        // Line terminators are decoded as they are, so the same number of
+        // lines is discarded from `text`
+        let num_lines = self.bytes[..cut].iter().filter(|&&b| b == b'\n').count();
+        let text_cut = self.text.match_indices('\n').nth(num_lines - 1).unwrap().0 + 1;
+        self.text.drain(..text_cut);
+        self.discarded_len += text_cut;
+        self.bytes.drain(..cut);
+        self.decoded_len -= cut;
+        self.line_start -= cut;
+        self.reported_len -= cut;
+        self.truncated = true;
+    }
+
+    /// Get the decoded output. An incomplete character at the end is
+    /// included only if `finished`.
+    fn decode(&self, encoding: Encoding, finished: bool) -> String {
+        let mut text = self.text.clone();
+        if finished {
+            text.push_str(&encoding.decode(&self.bytes[self.decoded_len..]));
+        }
+        text
+    }
+}
+
+/// The lines captured from the both streams.
+#[derive(Default)]
+struct Lines {
+    lines: Vec<OutputLine>,
+    /// The indices into `lines` and the bytes of the lines that are not
+    /// terminated yet, which are decoded again as they grow.
+    unterminated: [Option<(usize, Vec<u8>)>; 2],
+}
+
+impl Lines {
+    fn append(&mut self, stream: Stream, time: Duration, mut chunk: &[u8], encoding: Encoding) {
+        while !chunk.is_empty() {
+            let (line, rest) = match chunk.iter().position(|&b| b == b'\n') {
+                Some(i) => (&chunk[..i], Some(&chunk[i + 1..])),
+                None => (chunk, None),
+            };
+
+            let Self {
+                lines,
+                unterminated,
+            } = self;
+            let unterminated = &mut unterminated[stream as usize];
+            let (i, bytes) = unterminated.get_or_insert_with(|| {
+                lines.push(OutputLine {
+                    stream,
+                    time,
+                    text: String::new(),
+                });
+                (lines.len() - 1, Vec::new())
+            });
+            bytes.extend_from_slice(line);
+            lines[*i].text = encoding.decode(bytes);
+
+            match rest {
+                Some(rest) => {
+                    *unterminated = None;
+                    chunk = rest;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+fn make_output(
+    status: Option<ExitStatus>,
+    started: Instant,
+    encoding: Encoding,
+    [stdout, stderr]: [&Capture; 2],
+    lines: &Lines,
+) -> CmdOutput {
+    CmdOutput {
+        duration: status.map(|_| started.elapsed()),
+        started,
+        start_time: SystemTime::now() - started.elapsed(),
+        status,
+        stdout: stdout.decode(encoding, status.is_some()),
+        stderr: stderr.decode(encoding, status.is_some()),
+        stdout_raw: stdout.bytes.clone(),
+        stderr_raw: stderr.bytes.clone(),
+        stdout_offset: stdout.discarded_len,
+        stdout_truncated: stdout.truncated,
+        stderr_truncated: stderr.truncated,
+        stdout_len: stdout.total_len,
+        stdout_num_lines: stdout.num_lines,
+        lines: lines.lines.clone(),
+        success: status.map(|status| status.success()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_options() -> Options {
+        Options {
+            encoding: Encoding::Utf8Lossy,
+            initial_delay: Duration::from_secs(0),
+            interval: Arc::new(Mutex::new(Duration::from_secs(1))),
+            max_output_bytes: 1 << 20,
+            interleave: true,
+            tee: None,
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn run_once_captures_stderr() {
+        let cmd: Vec<OsString> = ["sh", "-c", "echo out; echo err >&2"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+
+        let mut last = None;
+        run_once(cmd, &test_options(), |output| last = Some(output));
+        let output = last.unwrap().unwrap();
+
+        assert!(output.status.unwrap().success());
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+
+        let stderr_lines: Vec<_> = output
+            .lines
+            .iter()
+            .filter(|line| line.stream == Stream::Stderr)
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(stderr_lines, ["err"]);
+    }
+
+    #[test]
+    fn run_once_command_not_found() {
+        let cmd = vec![OsString::from("pvfilt-nonexistent-command")];
+
+        let mut last = None;
+        run_once(cmd, &test_options(), |output| last = Some(output));
+        let error = last.unwrap().err().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "command not found: pvfilt-nonexistent-command"
+        );
+    }
+
+    #[test]
+    fn run_once_streams_past_max_output_bytes() {
+        let cmd: Vec<OsString> = [
+            "sh",
+            "-c",
+            "i=0; while [ $i -lt 3000 ]; do i=$((i+1)); echo $i/3000; done",
+        ]
+        .iter()
+        .map(OsString::from)
+        .collect();
+        let options = Options {
+            max_output_bytes: 1000,
+            ..test_options()
+        };
+
+        // Record every line to check that none is missed
+        let mut analyzer = crate::analysis::Analyzer::new(crate::analysis::Options {
+            aggregate: crate::analysis::Aggregate::All,
+            ..Default::default()
+        });
+        let mut last = None;
+        run_once(cmd, &options, |output| {
+            let output = output.unwrap();
+            analyzer.process_output(&output);
+            last = Some(output);
+        });
+        let output = last.unwrap();
+
+        assert!(output.stdout_truncated);
+        assert!(output.stdout.len() < 3000);
+        assert!(output.stdout.ends_with("\n2999/3000\n3000/3000\n"));
+        assert_eq!(output.stdout_num_lines, 3000);
+
+        // The analyzer retains the latest 1000 samples
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        let expected: Vec<_> = (2001..=3000).map(f64::from).collect();
+        assert_eq!(values, expected);
+        assert!(analyzer.samples.iter().all(|s| s.max == Some(3000.0)));
+    }
+
+    #[test]
+    fn read_input_latest_values() {
+        use crate::analysis::{Aggregate, Analyzer};
+
+        /// Returns a line per `read` like a pipe written line by line.
+        struct LineByLine<'a>(&'a [u8]);
+
+        impl Read for LineByLine<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let len = self
+                    .0
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(self.0.len(), |i| i + 1);
+                let len = len.min(buf.len());
+                buf[..len].copy_from_slice(&self.0[..len]);
+                self.0 = &self.0[len..];
+                Ok(len)
+            }
+        }
+
+        let input = b"1/10\n5/10\n10/10\n";
+        let run = |input: &mut dyn Read| {
+            let mut analyzer = Analyzer::new(crate::analysis::Options {
+                aggregate: Aggregate::Latest,
+                ..Default::default()
+            });
+            read_input(input, &test_options(), |output| {
+                analyzer.process_output(&output.unwrap());
+            });
+            let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+            values
+        };
+
+        assert_eq!(run(&mut LineByLine(input)), [1.0, 5.0, 10.0]);
+
+        // The newest of the lines read at once
+        assert_eq!(run(&mut &input[..]), [10.0]);
+    }
+
+    #[test]
+    fn read_input_reports_every_line() {
+        let input: String = (1..=3000).map(|i| format!("{}/3000\n", i)).collect();
+        let options = Options {
+            max_output_bytes: 1000,
+            ..test_options()
+        };
+
+        let mut analyzer = crate::analysis::Analyzer::new(crate::analysis::Options {
+            aggregate: crate::analysis::Aggregate::All,
+            ..Default::default()
+        });
+        read_input(input.as_bytes(), &options, |output| {
+            analyzer.process_output(&output.unwrap());
+        });
+
+        let values: Vec<_> = analyzer.samples.iter().map(|s| s.value).collect();
+        let expected: Vec<_> = (2001..=3000).map(f64::from).collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn capture_decodes_split_characters() {
+        let mut capture = Capture::default();
+        let bytes = "añb\n".as_bytes();
+        capture.append(&bytes[..2], 100, Encoding::Utf8Lossy);
+        assert_eq!(capture.decode(Encoding::Utf8Lossy, false), "a");
+        assert_eq!(capture.decode(Encoding::Utf8Lossy, true), "a\u{FFFD}");
+        capture.append(&bytes[2..], 100, Encoding::Utf8Lossy);
+        assert_eq!(capture.decode(Encoding::Utf8Lossy, false), "añb\n");
+
+        capture.append(b"\xff\n", 100, Encoding::Utf8Lossy);
+        assert_eq!(
+            capture.decode(Encoding::Utf8Lossy, false),
+            "añb\n\u{FFFD}\n"
+        );
+    }
+
+    #[test]
+    fn parse_interval_formats() {
+        assert_eq!(parse_interval("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_interval("0.5"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_interval("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_interval("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("-1").is_err());
+        assert!(parse_interval("soon").is_err());
     }
 }
@@ -0,0 +1,268 @@
+//! Saves and restores samples across invocations.
+//!
+//! A session file is a TOML document:
+//!
+//! ```toml
+//! command = "dmsetup status"
+//! parse = "number"
+//! aggregate = "latest"
+//!
+//! [[samples]]
+//! time = 1577836800.0
+//! value = 42.0
+//! max = 100.0
+//! series = { errors = 2.0 }
+//! ```
+//!
+//! `time` is the UNIX time in seconds. `Sample::instant` is reconstructed
+//! from it relative to the time the session is loaded. `series` maps the
+//! names of the extra series to their values.
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use toml::{value::Table, Value};
+
+use crate::analysis::Sample;
+
+pub struct Session {
+    /// The command the samples were collected from.
+    pub command: String,
+    /// The name of the `ParseMode` used.
+    pub parse_mode: String,
+    /// The name of the `Aggregate` used.
+    pub aggregate: String,
+    /// The names of the extra series, in the order of `Sample::series`.
+    pub series_names: Vec<String>,
+    pub samples: Vec<Sample>,
+}
+
+impl Session {
+    /// Reorder `Sample::series` to match `names`, the extra series of the
+    /// current pattern. The series not in `names` are dropped.
+    pub fn match_series(&mut self, names: &[&str]) {
+        let indices: Vec<_> = names
+            .iter()
+            .map(|name| self.series_names.iter().position(|n| n == name))
+            .collect();
+        for sample in &mut self.samples {
+            sample.series = indices
+                .iter()
+                .map(|i| sample.series.get((*i)?).copied()?)
+                .collect();
+        }
+        self.series_names = names.iter().map(|&name| name.to_owned()).collect();
+    }
+}
+
+pub fn save(path: &Path, session: &Session) -> Result<(), io::Error> {
+    let samples = session
+        .samples
+        .iter()
+        .map(|sample| {
+            let mut table = Table::new();
+            table.insert("time".to_owned(), Value::Float(unix_time(sample.time)));
+            table.insert("value".to_owned(), Value::Float(sample.value));
+            if let Some(max) = sample.max {
+                table.insert("max".to_owned(), Value::Float(max));
+            }
+            let series: Table = (session.series_names.iter())
+                .zip(&sample.series)
+                .filter_map(|(name, value)| Some((name.clone(), Value::Float((*value)?))))
+                .collect();
+            if !series.is_empty() {
+                table.insert("series".to_owned(), Value::Table(series));
+            }
+            Value::Table(table)
+        })
+        .collect();
+
+    let mut doc = Table::new();
+    doc.insert("command".to_owned(), Value::String(session.command.clone()));
+    doc.insert(
+        "parse".to_owned(),
+        Value::String(session.parse_mode.clone()),
+    );
+    doc.insert(
+        "aggregate".to_owned(),
+        Value::String(session.aggregate.clone()),
+    );
+    doc.insert("samples".to_owned(), Value::Array(samples));
+
+    let text = toml::to_string(&doc).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    fs::write(path, text)
+}
+
+pub fn load(path: &Path) -> Result<Session, io::Error> {
+    let invalid_data = |msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+
+    let text = fs::read_to_string(path)?;
+    let doc: Table = toml::from_str(&text).map_err(|e| invalid_data(e.to_string()))?;
+
+    let get_str = |key: &str| {
+        doc.get(key)
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .ok_or_else(|| invalid_data(format!("`{}` is missing or not a string", key)))
+    };
+
+    // The names of the extra series in the order of first appearance
+    let mut series_names: Vec<String> = Vec::new();
+
+    let now = (Instant::now(), SystemTime::now());
+    let samples = doc
+        .get("samples")
+        .and_then(Value::as_array)
+        .map_or(&[][..], |samples| &samples[..])
+        .iter()
+        .map(|sample| {
+            let get_f64 = |key: &str| sample.get(key).and_then(Value::as_float);
+            let (time, value) = get_f64("time")
+                .zip(get_f64("value"))
+                .ok_or_else(|| invalid_data("a sample lacks `time` or `value`".to_owned()))?;
+            if !value.is_finite() {
+                return Err(invalid_data(format!("invalid value: {}", value)));
+            }
+            let time = Some(time)
+                .filter(|time| time.is_finite() && *time < u64::MAX as f64)
+                .map(|time| Duration::from_secs_f64(time.max(0.0)))
+                .and_then(|d| UNIX_EPOCH.checked_add(d))
+                .ok_or_else(|| invalid_data(format!("invalid time: {}", time)))?;
+
+            let mut series = vec![None; series_names.len()];
+            if let Some(table) = sample.get("series") {
+                let table = table
+                    .as_table()
+                    .ok_or_else(|| invalid_data("`series` is not a table".to_owned()))?;
+                for (name, value) in table {
+                    let value = value.as_float().ok_or_else(|| {
+                        invalid_data(format!("series `{}` is not a number", name))
+                    })?;
+                    let i = series_names.iter().position(|n| n == name);
+                    let i = i.unwrap_or_else(|| {
+                        series_names.push(name.clone());
+                        series.push(None);
+                        series_names.len() - 1
+                    });
+                    series[i] = Some(value);
+                }
+            }
+
+            Ok(Sample {
+                instant: instant_at(time, now),
+                time,
+                value,
+                max: get_f64("max"),
+                line: None,
+                series,
+            })
+        })
+        .collect::<Result<_, io::Error>>()?;
+
+    Ok(Session {
+        command: get_str("command")?,
+        parse_mode: get_str("parse")?,
+        aggregate: get_str("aggregate")?,
+        series_names,
+        samples,
+    })
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Convert `time` to an `Instant` using a pair of the current `Instant` and
+/// `SystemTime` as a reference. Times in the future are clamped to the present.
+fn instant_at(time: SystemTime, (now_instant, now_time): (Instant, SystemTime)) -> Instant {
+    let age = now_time.duration_since(time).unwrap_or_default();
+    now_instant.checked_sub(age).unwrap_or(now_instant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pvfilt-{}-{}.toml", std::process::id(), name))
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+        let sample = |i: u64, series| Sample {
+            instant: Instant::now(),
+            time: time + Duration::from_secs(i),
+            value: i as f64,
+            max: Some(100.0).filter(|_| i > 0),
+            line: Some("discarded".into()),
+            series,
+        };
+        let session = Session {
+            command: "make -j4".to_owned(),
+            parse_mode: "number".to_owned(),
+            aggregate: "first".to_owned(),
+            series_names: vec!["errors".to_owned(), "skipped".to_owned()],
+            samples: vec![
+                sample(0, vec![Some(1.0), None]),
+                sample(1, vec![None, Some(2.5)]),
+                sample(2, vec![Some(3.0), Some(4.0)]),
+            ],
+        };
+
+        let path = temp_path("round-trip");
+        save(&path, &session).unwrap();
+        let mut loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.command, session.command);
+        assert_eq!(loaded.parse_mode, session.parse_mode);
+        assert_eq!(loaded.aggregate, session.aggregate);
+
+        // The names are recovered in the order of first appearance
+        loaded.match_series(&["errors", "skipped"]);
+        assert_eq!(loaded.samples.len(), session.samples.len());
+        for (loaded, saved) in loaded.samples.iter().zip(&session.samples) {
+            assert_eq!(loaded.time, saved.time);
+            assert_eq!(loaded.value, saved.value);
+            assert_eq!(loaded.max, saved.max);
+            assert_eq!(loaded.line, None);
+            assert_eq!(loaded.series, saved.series);
+        }
+
+        loaded.match_series(&["skipped", "unknown"]);
+        assert_eq!(loaded.samples[2].series, [Some(4.0), None]);
+    }
+
+    #[test]
+    fn load_rejects_invalid_time() {
+        for time in &["inf", "nan", "1e300"] {
+            let path = temp_path(&format!("time-{}", time));
+            fs::write(
+                &path,
+                format!(
+                    "command = \"\"\nparse = \"number\"\naggregate = \"first\"\n\
+                     [[samples]]\ntime = {}\nvalue = 1.0\n",
+                    time
+                ),
+            )
+            .unwrap();
+            let result = load(&path);
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                result.err().map(|e| e.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "{}",
+                time
+            );
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Renders charts of samples as standalone SVG images.
+use humantime::format_duration;
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    analysis::{self, Sample},
+    draw::{ticks, value_range},
+    unit::{self, Unit},
+};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 520.0;
+/// The room for the y labels.
+const MARGIN_LEFT: f64 = 90.0;
+const MARGIN_RIGHT: f64 = 20.0;
+/// The room for the title.
+const MARGIN_TOP: f64 = 40.0;
+/// The height of each chart, excluding the x labels.
+const CHART_HEIGHT: f64 = 190.0;
+/// The room for the x labels below each chart.
+const CHART_GAP: f64 = 50.0;
+const NUM_TICKS: usize = 5;
+
+/// Write a chart of the values and the rate of `samples` over time.
+pub fn write_chart(
+    out: &mut impl Write,
+    title: &str,
+    samples: &VecDeque<Sample>,
+    unit: &Unit,
+    precision: Option<usize>,
+    smooth: Option<analysis::Smoothing>,
+) -> io::Result<()> {
+    let first = samples.front().map(|s| s.instant);
+    // (elapsed, value)
+    let data: Vec<_> = samples
+        .iter()
+        .map(|s| {
+            let elapsed = first.map_or(0.0, |first| s.instant.duration_since(first).as_secs_f64());
+            (elapsed, s.value)
+        })
+        .collect();
+    let data_rate: Vec<_> = analysis::analyze_rate(data.iter().cloned()).collect();
+    let data_rate = match smooth {
+        Some(smoothing) => smoothing.apply(data_rate),
+        None => data_rate,
+    };
+    let max = samples.back().and_then(|s| s.max);
+
+    let time_range = [0.0, data.last().map_or(0.0, |&(t, _)| t).max(1.0)];
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"12\">",
+        w = WIDTH,
+        h = HEIGHT
+    )?;
+    writeln!(
+        out,
+        "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n\
+         <text x=\"{}\" y=\"24\" font-size=\"16\" text-anchor=\"middle\">{}</text>",
+        WIDTH / 2.0,
+        escape(title)
+    )?;
+
+    let value_chart = Chart {
+        top: MARGIN_TOP,
+        x_range: time_range,
+        y_range: value_range(data.iter().map(|s| s.1).chain(max)),
+    };
+    value_chart.write_axes(out, "Value", |v| unit.format_value(v, precision))?;
+    if let Some(max) = max {
+        let y = value_chart.y(max);
+        writeln!(
+            out,
+            "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"gray\" \
+             stroke-dasharray=\"4 4\"/>",
+            MARGIN_LEFT,
+            WIDTH - MARGIN_RIGHT,
+            y = y
+        )?;
+    }
+    value_chart.write_line(out, &data, "green")?;
+
+    let rate_chart = Chart {
+        top: MARGIN_TOP + CHART_HEIGHT + CHART_GAP,
+        x_range: time_range,
+        y_range: value_range(data_rate.iter().map(|s| s.1)),
+    };
+    rate_chart.write_axes(out, "Rate (per second)", |v| {
+        unit.format_value(v, precision)
+    })?;
+    rate_chart.write_line(out, &data_rate, "blue")?;
+
+    writeln!(out, "</svg>")
+}
+
+struct Chart {
+    top: f64,
+    x_range: [f64; 2],
+    y_range: [f64; 2],
+}
+
+impl Chart {
+    fn x(&self, t: f64) -> f64 {
+        let width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+        round(MARGIN_LEFT + (t - self.x_range[0]) / (self.x_range[1] - self.x_range[0]) * width)
+    }
+
+    fn y(&self, v: f64) -> f64 {
+        round(
+            self.top + CHART_HEIGHT
+                - (v - self.y_range[0]) / (self.y_range[1] - self.y_range[0]) * CHART_HEIGHT,
+        )
+    }
+
+    /// Write the frame, the ticks, and the labels.
+    fn write_axes(
+        &self,
+        out: &mut impl Write,
+        name: &str,
+        format_y: impl Fn(f64) -> String,
+    ) -> io::Result<()> {
+        let bottom = self.top + CHART_HEIGHT;
+        writeln!(
+            out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\"/>",
+            MARGIN_LEFT,
+            self.top,
+            WIDTH - MARGIN_LEFT - MARGIN_RIGHT,
+            CHART_HEIGHT
+        )?;
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\" font-weight=\"bold\">{}</text>",
+            MARGIN_LEFT,
+            self.top - 6.0,
+            escape(name)
+        )?;
+
+        for t in ticks(self.x_range, NUM_TICKS) {
+            let x = self.x(t);
+            // Short spans need fractional seconds to tell the ticks apart
+            let label = if self.x_range[1] - self.x_range[0] >= 10.0 {
+                format_duration(Duration::from_secs(t.round() as u64)).to_string()
+            } else {
+                format!("{}s", unit::format_number(t, None))
+            };
+            writeln!(
+                out,
+                "<line x1=\"{x}\" y1=\"{}\" x2=\"{x}\" y2=\"{}\" stroke=\"black\"/>\
+                 <text x=\"{x}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                bottom,
+                bottom + 4.0,
+                bottom + 18.0,
+                label,
+                x = x
+            )?;
+        }
+        writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">Elapsed time</text>",
+            self.x((self.x_range[0] + self.x_range[1]) / 2.0),
+            bottom + 34.0
+        )?;
+
+        for v in ticks(self.y_range, NUM_TICKS) {
+            let y = self.y(v);
+            writeln!(
+                out,
+                "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"black\"/>\
+                 <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+                MARGIN_LEFT - 4.0,
+                MARGIN_LEFT,
+                MARGIN_LEFT - 6.0,
+                y + 4.0,
+                escape(&format_y(v)),
+                y = y
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write a polyline through `data`, which is `(x, y)` pairs.
+    fn write_line(&self, out: &mut impl Write, data: &[(f64, f64)], color: &str) -> io::Result<()> {
+        let points: Vec<_> = data
+            .iter()
+            .filter(|(_, v)| v.is_finite())
+            .map(|&(t, v)| format!("{},{}", self.x(t), self.y(v)))
+            .collect();
+        writeln!(
+            out,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+            points.join(" "),
+            color
+        )
+    }
+}
+
+/// Round a coordinate to keep the output compact.
+fn round(x: f64) -> f64 {
+    (x * 10.0).round() / 10.0
+}
+
+/// Escape the characters with special meanings in XML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
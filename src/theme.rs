@@ -0,0 +1,96 @@
+//! Color themes for the user interface.
+use std::str::FromStr;
+use tui::style::Color;
+
+/// The colors assigned to the roles of UI elements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// The rate line and positive indications.
+    pub line: Color,
+    /// The stderr pane.
+    pub stderr: Color,
+    /// Borders, titles, and labels.
+    pub border: Color,
+    /// The help window and notifications.
+    pub accent: Color,
+    /// Indications that need attention, e.g., a command that is still running.
+    pub warning: Color,
+    /// Errors and negative indications.
+    pub error: Color,
+    /// The filled part of the progress gauge.
+    pub gauge: Color,
+    /// The unfilled part of the progress gauge.
+    pub gauge_background: Color,
+    /// The extra series captured by named groups, used in turn.
+    pub series: [Color; 4],
+}
+
+impl Theme {
+    pub const NAMES: &'static [&'static str] = &["default", "solarized", "mono"];
+
+    pub const DEFAULT: Theme = Theme {
+        line: Color::Green,
+        stderr: Color::Yellow,
+        border: Color::DarkGray,
+        accent: Color::LightCyan,
+        warning: Color::Yellow,
+        error: Color::Red,
+        gauge: Color::White,
+        gauge_background: Color::Black,
+        series: [Color::Cyan, Color::Magenta, Color::Blue, Color::LightRed],
+    };
+
+    /// The accent colors of Solarized in the 256-color palette.
+    pub const SOLARIZED: Theme = Theme {
+        line: Color::Indexed(64),
+        stderr: Color::Indexed(136),
+        border: Color::Indexed(240),
+        accent: Color::Indexed(37),
+        warning: Color::Indexed(166),
+        error: Color::Indexed(160),
+        gauge: Color::Indexed(33),
+        gauge_background: Color::Indexed(235),
+        series: [
+            Color::Indexed(37),
+            Color::Indexed(125),
+            Color::Indexed(61),
+            Color::Indexed(166),
+        ],
+    };
+
+    /// No colors at all.
+    pub const MONO: Theme = Theme {
+        line: Color::Reset,
+        stderr: Color::Reset,
+        border: Color::Reset,
+        accent: Color::Reset,
+        warning: Color::Reset,
+        error: Color::Reset,
+        gauge: Color::Reset,
+        gauge_background: Color::Reset,
+        series: [Color::Reset; 4],
+    };
+
+    /// Get the theme to use if none is specified. `MONO` is chosen if the
+    /// `NO_COLOR` environment variable is set (<https://no-color.org>).
+    pub fn from_env() -> Theme {
+        if std::env::var_os("NO_COLOR").map_or(false, |x| !x.is_empty()) {
+            Theme::MONO
+        } else {
+            Theme::DEFAULT
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Theme::DEFAULT),
+            "solarized" => Ok(Theme::SOLARIZED),
+            "mono" => Ok(Theme::MONO),
+            _ => Err(format!("unknown theme: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{convert::Infallible, str::FromStr};
+
+/// The unit of the values extracted from a command's output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unit {
+    /// Dimensionless; values are displayed as they are.
+    None,
+    /// Bytes, displayed with decimal prefixes (kB, MB, GB, ...).
+    Bytes,
+    /// Bytes, displayed with binary prefixes (KiB, MiB, GiB, ...).
+    IBytes,
+    /// An arbitrary suffix appended to values without rescaling.
+    Custom(String),
+    /// Seconds, displayed in the `HH:MM:SS` format.
+    Duration,
+}
+
+impl Default for Unit {
+    fn default() -> Self {
+        Unit::None
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => Unit::None,
+            "bytes" => Unit::Bytes,
+            "ibytes" => Unit::IBytes,
+            _ => Unit::Custom(s.to_owned()),
+        })
+    }
+}
+
+const DECIMAL_PREFIXES: &[&str] = &["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+const BINARY_PREFIXES: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+impl Unit {
+    /// Format a value as read from the command's output, e.g., `42/100`.
+    /// `precision` is the number of decimal places, chosen by `format_number`
+    /// if `None`.
+    pub fn format_value(&self, x: f64, precision: Option<usize>) -> String {
+        match self {
+            Unit::None => format_number(x, precision),
+            Unit::Custom(suffix) => format!("{} {}", format_number(x, precision), suffix),
+            Unit::Bytes | Unit::IBytes => self.format_scaled(x, precision),
+            Unit::Duration => format_clock(x),
+        }
+    }
+
+    /// Format a derived quantity such as a rate or an axis bound.
+    /// `precision` is the number of decimal places.
+    pub fn format_approx(&self, x: f64, precision: Option<usize>) -> String {
+        match self {
+            Unit::None => format!("{:.*e}", precision.unwrap_or(4), x),
+            Unit::Custom(suffix) => format!("{:.*e} {}", precision.unwrap_or(4), x, suffix),
+            Unit::Bytes | Unit::IBytes => self.format_scaled(x, precision),
+            Unit::Duration if x.abs() < 60.0 => format!("{:.*}s", precision.unwrap_or(2), x),
+            Unit::Duration => format_clock(x),
+        }
+    }
+
+    fn format_scaled(&self, x: f64, precision: Option<usize>) -> String {
+        let (base, prefixes) = match self {
+            Unit::Bytes => (1000.0, DECIMAL_PREFIXES),
+            Unit::IBytes => (1024.0, BINARY_PREFIXES),
+            _ => unreachable!(),
+        };
+
+        let precision_at = |i| precision.unwrap_or(if i == 0 { 0 } else { 1 });
+
+        // Compare the rounded value so that, e.g., `999.95 kB` becomes
+        // `1.0 MB` rather than `1000.0 kB`
+        let mut scaled = x;
+        let mut i = 0;
+        while round_to(scaled.abs(), precision_at(i)) >= base && i + 1 < prefixes.len() {
+            scaled /= base;
+            i += 1;
+        }
+
+        format!("{:.*} {}", precision_at(i), scaled, prefixes[i])
+    }
+}
+
+/// Format a number with `precision` decimal places. If `None`, the number is
+/// rounded to about four significant digits (keeping the integral part) and
+/// trailing zeros are removed, e.g., `42.000000001` → `42`.
+pub fn format_number(x: f64, precision: Option<usize>) -> String {
+    if let Some(precision) = precision {
+        return format!("{:.*}", precision, x);
+    }
+    if !x.is_finite() {
+        return format!("{}", x);
+    }
+
+    let int_digits = if x.abs() >= 1.0 {
+        x.abs().log10().floor() as usize + 1
+    } else {
+        1
+    };
+    let out = format!("{:.*}", 4usize.saturating_sub(int_digits), x);
+    let out = if out.contains('.') {
+        out.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &out
+    };
+    // A tiny negative number is rounded to `-0`
+    if out == "-0" { "0" } else { out }.to_owned()
+}
+
+/// Round `x` to `precision` decimal places.
+fn round_to(x: f64, precision: usize) -> f64 {
+    let scale = 10f64.powi(precision as i32);
+    (x * scale).round() / scale
+}
+
+/// Format seconds in the `[-]HH:MM:SS[.fff]` format.
+fn format_clock(x: f64) -> String {
+    let millis = (x.abs() * 1000.0).round() as u64;
+    let sign = if x < 0.0 && millis > 0 { "-" } else { "" };
+    let (whole, millis) = (millis / 1000, millis % 1000);
+    let (hours, minutes, seconds) = (whole / 3600, whole / 60 % 60, whole % 60);
+
+    let mut out = format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds);
+    if millis > 0 {
+        out.push_str(format!(".{:03}", millis).trim_end_matches('0'));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_number_significant_digits() {
+        assert_eq!(format_number(0.0, None), "0");
+        assert_eq!(format_number(42.000000001, None), "42");
+        assert_eq!(format_number(1.23456, None), "1.235");
+        assert_eq!(format_number(123.456, None), "123.5");
+        assert_eq!(format_number(123456.7, None), "123457");
+        assert_eq!(format_number(0.0012, None), "0.001");
+        assert_eq!(format_number(9.99995, None), "10");
+        assert_eq!(format_number(f64::INFINITY, None), "inf");
+    }
+
+    #[test]
+    fn format_number_negative() {
+        assert_eq!(format_number(-1.23456, None), "-1.235");
+        assert_eq!(format_number(-1500.0, None), "-1500");
+        assert_eq!(format_number(-0.00001, None), "0");
+        assert_eq!(format_number(-2.5, Some(2)), "-2.50");
+    }
+
+    #[test]
+    fn format_scaled_boundaries() {
+        let bytes = Unit::Bytes;
+        assert_eq!(bytes.format_value(0.0, None), "0 B");
+        assert_eq!(bytes.format_value(999.0, None), "999 B");
+        assert_eq!(bytes.format_value(1000.0, None), "1.0 kB");
+        assert_eq!(bytes.format_value(1_500_000.0, None), "1.5 MB");
+        assert_eq!(bytes.format_value(-2_000.0, None), "-2.0 kB");
+        assert_eq!(bytes.format_value(1e21, None), "1000.0 EB");
+
+        let ibytes = Unit::IBytes;
+        assert_eq!(ibytes.format_value(1023.0, None), "1023 B");
+        assert_eq!(ibytes.format_value(1024.0, None), "1.0 KiB");
+        assert_eq!(ibytes.format_value(1536.0 * 1024.0, Some(2)), "1.50 MiB");
+    }
+
+    #[test]
+    fn format_scaled_rounding_carry() {
+        let bytes = Unit::Bytes;
+        assert_eq!(bytes.format_value(999.6, None), "1.0 kB");
+        assert_eq!(bytes.format_value(999_950.0, None), "1.0 MB");
+        assert_eq!(bytes.format_value(999_950.0, Some(2)), "999.95 kB");
+        assert_eq!(bytes.format_value(999_995.0, Some(2)), "1.00 MB");
+        assert_eq!(bytes.format_value(999_940.0, None), "999.9 kB");
+        assert_eq!(bytes.format_value(-999_950.0, None), "-1.0 MB");
+        assert_eq!(Unit::IBytes.format_value(1023.99, Some(1)), "1.0 KiB");
+    }
+
+    #[test]
+    fn format_clock_fields() {
+        assert_eq!(format_clock(0.0), "00:00:00");
+        assert_eq!(format_clock(59.0), "00:00:59");
+        assert_eq!(format_clock(3661.5), "01:01:01.5");
+        assert_eq!(format_clock(100.0 * 3600.0), "100:00:00");
+        assert_eq!(format_clock(59.9996), "00:01:00");
+        assert_eq!(format_clock(-90.25), "-00:01:30.25");
+        assert_eq!(format_clock(-0.0001), "00:00:00");
+    }
+}